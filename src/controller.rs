@@ -1,52 +1,268 @@
 use std::sync::Mutex;
-use std::{collections::BTreeMap, future::Future};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+};
 
+mod formatter;
+mod health;
 mod sanitizer;
+#[path = "controller/发布最新.rs"]
+mod 发布最新;
 #[path = "controller/发送.rs"]
 mod 发送;
+#[path = "controller/已发送.rs"]
+mod 已发送;
+#[path = "controller/广播.rs"]
+mod 广播;
 #[path = "controller/所有频道.rs"]
 mod 所有频道;
+#[path = "controller/更新.rs"]
+mod 更新;
+#[path = "controller/最新列表.rs"]
+mod 最新列表;
+#[path = "controller/最近错误.rs"]
+mod 最近错误;
+#[path = "controller/清空.rs"]
+mod 清空;
 #[path = "controller/爬取.rs"]
 mod 爬取;
+#[path = "controller/诊断.rs"]
+mod 诊断;
+#[path = "controller/详情.rs"]
+mod 详情;
+#[path = "controller/重发.rs"]
+mod 重发;
+
+pub use formatter::{DefaultPostFormatter, PostFormatter};
+pub use health::HealthStatus;
+pub use sanitizer::Sanitizer;
+pub use 发送::{HtmlProcessConfig, TableMode};
 
 use crate::crawler::Crawler;
 use crate::post::{DailyPost, DailyPostDate};
-use crate::qbot::QBotApiClient;
+use crate::qbot::ws::SharedWsDiagnostics;
+use crate::qbot::{ChannelId, GuildId, QBotApiClient};
 
 pub trait Controller {
-    fn 所有频道(&self, guild_id: &str) -> impl Future<Output = String> + Send;
+    fn 所有频道(
+        &self,
+        guild_id: &GuildId,
+        keyword: &str,
+    ) -> impl Future<Output = String> + Send;
     fn 爬取(&self, href: &str) -> impl Future<Output = String> + Send;
-    fn 发送(&self, channel_id: &str, date: DailyPostDate) -> impl Future<Output = String> + Send;
+    fn 爬取最新(&self) -> impl Future<Output = String> + Send;
+    /// Lists the `n` newest posts on the category page without crawling them.
+    fn 最新列表(&self, n: usize) -> impl Future<Output = String> + Send;
+    /// Drops all crawled posts that are waiting to be sent.
+    fn 清空(&self) -> impl Future<Output = String> + Send;
+    /// Sends the post to `target_channel_ids`, or to the news channels configured for `guild_id`
+    /// if it's empty.
+    fn 发送(
+        &self,
+        guild_id: &GuildId,
+        channel_id: &ChannelId,
+        date: DailyPostDate,
+        target_channel_ids: &[ChannelId],
+    ) -> impl Future<Output = String> + Send;
+    /// Crawls the latest post and sends it like 发送 does without target channels.
+    fn 发布最新(
+        &self,
+        guild_id: &GuildId,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = String> + Send;
+    /// Sends the most recently sent post again to `channel_id`.
+    fn 重发(&self, channel_id: &ChannelId) -> impl Future<Output = String> + Send;
+    /// Lists the threads sent so far, by date.
+    fn 已发送(&self) -> impl Future<Output = String> + Send;
+    /// Re-crawls a sent post and replaces the threads it was sent as.
+    fn 更新(&self, date: DailyPostDate) -> impl Future<Output = String> + Send;
+    /// Describes a queued post and whether its HTML can be processed, without sending it.
+    fn 详情(&self, date: DailyPostDate) -> impl Future<Output = String> + Send;
+    /// Sends `content` to the news channel of `guild_id`.
+    fn 广播(&self, guild_id: &GuildId, content: &str) -> impl Future<Output = String> + Send;
+    fn 最近错误(&self) -> impl Future<Output = String> + Send;
+    fn 诊断(&self) -> impl Future<Output = String> + Send;
 }
 
+const DEFAULT_SITE_BASE_URL: &str = "https://rustcc.cn";
+
 pub struct ControllerImpl<A, C> {
     crawler: C,
     posts: Mutex<BTreeMap<DailyPostDate, DailyPost>>,
-    news_channel_id: String,
+    sending: Mutex<BTreeSet<DailyPostDate>>,
+    sent_threads: Mutex<BTreeMap<DailyPostDate, 发送::SentThreads>>,
+    // Kept for 重发, since sending takes the post out of `posts`.
+    last_sent: Mutex<Option<DailyPost>>,
+    news_channel_id: ChannelId,
+    guild_news_channel_ids: HashMap<GuildId, ChannelId>,
+    mirror_channel_ids: Vec<ChannelId>,
+    // Channel types looked up by 发送, which don't change for an existing channel.
+    channel_types: Mutex<HashMap<ChannelId, u32>>,
     api_client: A,
+    dry_run: bool,
+    formatter: Box<dyn PostFormatter + Send + Sync>,
+    site_base_url: String,
+    html_config: HtmlProcessConfig,
+    send_summary: bool,
+    last_error: Mutex<Option<最近错误::LastError>>,
+    ws_diagnostics: SharedWsDiagnostics,
+    sanitizer: Sanitizer,
 }
 
 impl<A, C> ControllerImpl<A, C> {
-    pub fn new(api_client: A, crawler: C, news_channel_id: String) -> Self {
+    pub fn new(api_client: A, crawler: C, news_channel_id: ChannelId, dry_run: bool) -> Self {
         Self {
             crawler,
             posts: Default::default(),
+            sending: Default::default(),
+            sent_threads: Default::default(),
+            last_sent: Default::default(),
             news_channel_id,
+            guild_news_channel_ids: Default::default(),
+            mirror_channel_ids: vec![],
+            channel_types: Default::default(),
             api_client,
+            dry_run,
+            formatter: Box::new(DefaultPostFormatter),
+            site_base_url: DEFAULT_SITE_BASE_URL.into(),
+            html_config: Default::default(),
+            send_summary: false,
+            last_error: Default::default(),
+            ws_diagnostics: Default::default(),
+            sanitizer: Default::default(),
+        }
+    }
+
+    /// News channels for guilds that don't use the default one, keyed by guild id.
+    pub fn with_guild_news_channel_ids(
+        self,
+        guild_news_channel_ids: HashMap<GuildId, ChannelId>,
+    ) -> Self {
+        Self {
+            guild_news_channel_ids,
+            ..self
+        }
+    }
+
+    fn news_channel_id_for(&self, guild_id: &GuildId) -> &ChannelId {
+        self.guild_news_channel_ids
+            .get(guild_id)
+            .unwrap_or(&self.news_channel_id)
+    }
+
+    /// Channels that receive a copy of every digest sent to the news channel.
+    pub fn with_mirror_channel_ids(self, mirror_channel_ids: Vec<ChannelId>) -> Self {
+        Self {
+            mirror_channel_ids,
+            ..self
+        }
+    }
+
+    /// The site posts are crawled from, which should match the crawler's base URL.
+    pub fn with_site_base_url(self, site_base_url: impl Into<String>) -> Self {
+        Self {
+            site_base_url: site_base_url.into(),
+            ..self
+        }
+    }
+
+    pub fn with_html_config(self, html_config: HtmlProcessConfig) -> Self {
+        Self {
+            html_config,
+            ..self
+        }
+    }
+
+    /// Also sends a plain-text summary with the original link next to each thread, since threads
+    /// are easy to miss in some clients.
+    pub fn with_summary_message(self, send_summary: bool) -> Self {
+        Self {
+            send_summary,
+            ..self
+        }
+    }
+
+    pub fn with_ws_diagnostics(self, ws_diagnostics: SharedWsDiagnostics) -> Self {
+        Self {
+            ws_diagnostics,
+            ..self
+        }
+    }
+
+    pub fn with_sanitizer(self, sanitizer: Sanitizer) -> Self {
+        Self { sanitizer, ..self }
+    }
+
+    pub fn with_formatter(self, formatter: impl PostFormatter + Send + Sync + 'static) -> Self {
+        Self {
+            formatter: Box::new(formatter),
+            ..self
         }
     }
 }
 
 impl<A: QBotApiClient + Sync, C: Crawler + Sync> Controller for ControllerImpl<A, C> {
-    fn 所有频道(&self, guild_id: &str) -> impl Future<Output = String> + Send {
-        async { self.所有频道(guild_id).await }
+    async fn 所有频道(&self, guild_id: &GuildId, keyword: &str) -> String {
+        self.所有频道(guild_id, keyword).await
+    }
+
+    async fn 爬取(&self, href: &str) -> String {
+        self.爬取(href).await
+    }
+
+    async fn 爬取最新(&self) -> String {
+        self.爬取最新().await
+    }
+
+    async fn 最新列表(&self, n: usize) -> String {
+        self.最新列表(n).await
+    }
+
+    async fn 清空(&self) -> String {
+        self.清空().await
+    }
+
+    async fn 发送(
+        &self,
+        guild_id: &GuildId,
+        channel_id: &ChannelId,
+        date: DailyPostDate,
+        target_channel_ids: &[ChannelId],
+    ) -> String {
+        self.发送(guild_id, channel_id, date, target_channel_ids)
+            .await
+    }
+
+    async fn 发布最新(&self, guild_id: &GuildId, channel_id: &ChannelId) -> String {
+        self.发布最新(guild_id, channel_id).await
+    }
+
+    async fn 重发(&self, channel_id: &ChannelId) -> String {
+        self.重发(channel_id).await
+    }
+
+    async fn 已发送(&self) -> String {
+        self.已发送().await
+    }
+
+    async fn 更新(&self, date: DailyPostDate) -> String {
+        self.更新(date).await
+    }
+
+    async fn 详情(&self, date: DailyPostDate) -> String {
+        self.详情(date).await
+    }
+
+    async fn 广播(&self, guild_id: &GuildId, content: &str) -> String {
+        self.广播(guild_id, content).await
     }
 
-    fn 爬取(&self, href: &str) -> impl Future<Output = String> + Send {
-        async { self.爬取(href).await }
+    async fn 最近错误(&self) -> String {
+        self.最近错误().await
     }
 
-    fn 发送(&self, channel_id: &str, date: DailyPostDate) -> impl Future<Output = String> + Send {
-        async move { self.发送(channel_id, date).await }
+    async fn 诊断(&self) -> String {
+        self.诊断().await
     }
 }
@@ -0,0 +1,56 @@
+use crate::post::{DailyPost, ReadingStats};
+
+/// Lays out a post as a forum thread. Override individual parts to customize the digest; the
+/// defaults produce the original rustcc.cn layout.
+pub trait PostFormatter {
+    fn title(&self, post: &DailyPost) -> String {
+        format!("[{}] {}", post.date, post.title)
+    }
+
+    fn header(&self, post: &DailyPost) -> String {
+        let mut header = format!("<p>{} 发表于 {}</p>", post.author, post.publish_time);
+        if !post.tags.is_empty() {
+            header += &format!("<p>标签：{}</p>", post.tags.join("、"));
+        }
+        if self.include_reading_time() {
+            let stats = ReadingStats::from_html(&post.content_html);
+            header += &format!(
+                "<p>约 {} 字，阅读约 {} 分钟</p>",
+                stats.words, stats.minutes
+            );
+        }
+        header
+    }
+
+    fn footer(&self, _post: &DailyPost) -> String {
+        String::new()
+    }
+
+    fn include_original_link(&self) -> bool {
+        true
+    }
+
+    /// Adds the word count and reading time of the post to the default header.
+    fn include_reading_time(&self) -> bool {
+        false
+    }
+
+    /// `site_base_url` is the site the post was crawled from, which relative links resolve
+    /// against.
+    fn format_html(&self, post: &DailyPost, content_html: &str, site_base_url: &str) -> String {
+        let mut html = self.header(post);
+        if self.include_original_link() {
+            html += &format!(
+                r#"<p><a href="{site_base_url}{}">原文链接</a></p>"#,
+                post.href
+            );
+        }
+        html += content_html;
+        html += &self.footer(post);
+        html
+    }
+}
+
+pub struct DefaultPostFormatter;
+
+impl PostFormatter for DefaultPostFormatter {}
@@ -0,0 +1,121 @@
+use std::fmt::{self, Display};
+
+use super::ControllerImpl;
+use crate::crawler::{Crawler, CrawlerError};
+use crate::qbot::QBotApiClient;
+
+/// Whether the controller can reach its dependencies. `Err` holds why one can't be reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthStatus {
+    pub qq_api: Result<(), String>,
+    /// `None` if the crawler wasn't checked.
+    pub crawler: Option<Result<(), String>>,
+}
+
+impl HealthStatus {
+    pub fn is_healthy(&self) -> bool {
+        self.qq_api.is_ok() && self.crawler.as_ref().is_none_or(Result::is_ok)
+    }
+}
+
+impl Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn line(
+            f: &mut fmt::Formatter<'_>,
+            name: &str,
+            status: &Result<(), String>,
+        ) -> fmt::Result {
+            match status {
+                Ok(()) => writeln!(f, "{name}: ok"),
+                Err(e) => writeln!(f, "{name}: error: {e}"),
+            }
+        }
+        line(f, "qq_api", &self.qq_api)?;
+        if let Some(crawler) = &self.crawler {
+            line(f, "crawler", crawler)?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: QBotApiClient + Sync, C: Crawler + Sync> ControllerImpl<A, C> {
+    /// Checks that the QQ API can be reached and, if `check_crawler` is set, that the crawler can
+    /// fetch the news category.
+    pub async fn health(&self, check_crawler: bool) -> HealthStatus {
+        let qq_api = self
+            .api_client
+            .get_me()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        let crawler = if check_crawler {
+            Some(match self.crawler.fetch_news_category().await {
+                // The site answered, there is just nothing posted yet.
+                Ok(_) | Err(CrawlerError::NoPostsFound) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        } else {
+            None
+        };
+        HealthStatus { qq_api, crawler }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::{CrawlerImpl, InMemoryCrawler};
+    use crate::qbot::{InMemoryApiClient, MockAuthorizer, QBotApiClientImpl};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_healthy() {
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        let status = controller.health(true).await;
+        assert_eq!(
+            status,
+            HealthStatus {
+                qq_api: Ok(()),
+                crawler: Some(Ok(())),
+            }
+        );
+        assert!(status.is_healthy());
+        assert_eq!(status.to_string(), "qq_api: ok\ncrawler: ok\n");
+        assert_eq!(controller.health(false).await.crawler, None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_dependency() {
+        // Nothing listens on port 1, so connecting fails right away.
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            CrawlerImpl::new("http://127.0.0.1:1".into()),
+            "newsChannelId".into(),
+            false,
+        );
+        let status = controller.health(true).await;
+        assert!(status.qq_api.is_ok());
+        assert!(matches!(status.crawler, Some(Err(_))));
+        assert!(!status.is_healthy());
+
+        let controller = ControllerImpl::new(
+            QBotApiClientImpl::new(
+                "http://127.0.0.1:1".into(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        let status = controller.health(false).await;
+        assert!(status.qq_api.is_err());
+        assert!(!status.is_healthy());
+        assert!(status.to_string().starts_with("qq_api: error: "));
+    }
+}
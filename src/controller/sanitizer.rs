@@ -1,3 +1,100 @@
-pub(super) fn sanitize_message(message: String) -> String {
-    message.replace(".", "-")
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// Rewrites text echoed back to QQ, which rejects messages containing raw domains and the like.
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    pub dot_replacement: String,
+    /// Neutralize `@everyone`, `@here` and `<@...>` so echoed text can't ping anyone.
+    pub escape_mentions: bool,
+    /// Leave `http(s)://` URLs untouched.
+    pub preserve_urls: bool,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self {
+            dot_replacement: "-".into(),
+            escape_mentions: true,
+            preserve_urls: false,
+        }
+    }
+}
+
+impl Sanitizer {
+    pub fn sanitize(&self, text: &str) -> String {
+        static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+        if !self.preserve_urls {
+            return self.sanitize_segment(text);
+        }
+        let url_regex = URL_REGEX.get_or_init(|| Regex::new(r"https?://\S+").unwrap());
+        let mut output = String::with_capacity(text.len());
+        let mut last_end = 0;
+        for url in url_regex.find_iter(text) {
+            output += &self.sanitize_segment(&text[last_end..url.start()]);
+            output += url.as_str();
+            last_end = url.end();
+        }
+        output += &self.sanitize_segment(&text[last_end..]);
+        output
+    }
+
+    fn sanitize_segment(&self, text: &str) -> String {
+        let text = text.replace('.', &self.dot_replacement);
+        if !self.escape_mentions {
+            return text;
+        }
+        // A zero-width space keeps the text readable while breaking the mention syntax.
+        text.replace("@everyone", "@\u{200b}everyone")
+            .replace("@here", "@\u{200b}here")
+            .replace("<@", "<\u{200b}@")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_numbers() {
+        let sanitizer = Sanitizer::default();
+        assert_eq!(sanitizer.sanitize("Rust 1.77.0 发布"), "Rust 1-77-0 发布");
+        let sanitizer = Sanitizer {
+            dot_replacement: "。".into(),
+            ..Default::default()
+        };
+        assert_eq!(sanitizer.sanitize("Rust 1.77.0 发布"), "Rust 1。77。0 发布");
+    }
+
+    #[test]
+    fn test_urls() {
+        let text = "详见 https://blog.rust-lang.org/a.html 和 rustcc.cn";
+        assert_eq!(
+            Sanitizer::default().sanitize(text),
+            "详见 https://blog-rust-lang-org/a-html 和 rustcc-cn"
+        );
+        let sanitizer = Sanitizer {
+            preserve_urls: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sanitizer.sanitize(text),
+            "详见 https://blog.rust-lang.org/a.html 和 rustcc-cn"
+        );
+    }
+
+    #[test]
+    fn test_mentions() {
+        let text = "@everyone @here <@!123> <@&456>";
+        assert_eq!(
+            Sanitizer::default().sanitize(text),
+            "@\u{200b}everyone @\u{200b}here <\u{200b}@!123> <\u{200b}@&456>"
+        );
+        let sanitizer = Sanitizer {
+            escape_mentions: false,
+            ..Default::default()
+        };
+        assert_eq!(sanitizer.sanitize(text), text);
+    }
 }
@@ -0,0 +1,90 @@
+use super::ControllerImpl;
+use crate::crawler::Crawler;
+use crate::qbot::{ChannelId, GuildId, QBotApiClient};
+
+impl<A: QBotApiClient + Sync, C: Crawler + Sync> ControllerImpl<A, C> {
+    pub(super) async fn 发布最新(&self, guild_id: &GuildId, channel_id: &ChannelId) -> String {
+        let post = match self.crawler.fetch_latest_post().await {
+            Ok(post) => post,
+            Err(e) => {
+                self.record_error("发布最新", &e, None);
+                return format!("爬取失败: {}", e);
+            }
+        };
+        let date = post.date;
+        let crawl_reply = self.queue_post(post);
+        let send_reply = self.发送(guild_id, channel_id, date, &[]).await;
+        format!("{crawl_reply}\n{send_reply}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::InMemoryCrawler;
+    use crate::post::DailyPost;
+    use crate::qbot::model::Channel;
+    use crate::qbot::InMemoryApiClient;
+
+    use super::*;
+
+    fn api_client() -> InMemoryApiClient {
+        let api_client = InMemoryApiClient::new();
+        api_client.add_channel(Channel {
+            id: "newsChannelId".into(),
+            guild_id: "guildId".into(),
+            name: "日报".into(),
+            channel_type: Channel::TYPE_FORUM,
+        });
+        api_client
+    }
+
+    #[tokio::test]
+    async fn test_publish_latest() {
+        let post = DailyPost {
+            href: "/article?id=1".into(),
+            content_html: "<p>内容</p>".into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-11 16:16".into(),
+            published_at: None,
+            date: "2024-04-11".parse().unwrap(),
+            tags: vec![],
+            content_hash: 0,
+        };
+        let controller = ControllerImpl::new(
+            api_client(),
+            InMemoryCrawler::new(vec![post]),
+            "newsChannelId".into(),
+            false,
+        );
+
+        let res = controller
+            .发布最新(&"guildId".into(), &"channelId".into())
+            .await;
+        assert_eq!(
+            res,
+            "爬取成功: 2024-04-11 - TinyUFO\n发送成功: 2024-04-11 - TinyUFO"
+        );
+        let threads = controller.api_client.threads();
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].0, "newsChannelId");
+        assert!(controller.posts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_publish_latest_crawl_failure() {
+        let controller = ControllerImpl::new(
+            api_client(),
+            InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+
+        let res = controller
+            .发布最新(&"guildId".into(), &"channelId".into())
+            .await;
+        assert!(res.starts_with("爬取失败: "), "{res}");
+        assert!(controller.api_client.sent().is_empty());
+        assert!(controller.最近错误().await.contains("发布最新"));
+    }
+}
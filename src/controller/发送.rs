@@ -1,19 +1,99 @@
 use std::cell::RefCell;
-use std::future::Future;
+use std::collections::BTreeSet;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Mutex;
 
 use html5ever::tendril::TendrilSink;
 use html5ever::{local_name, namespace_url, ns, parse_fragment, serialize, QualName};
 use markup5ever_rcdom::{Node, NodeData, RcDom, SerializableHandle};
-use tracing::warn;
+use tracing::{info, warn};
 
 use super::ControllerImpl;
-use crate::controller::sanitizer::sanitize_message;
 use crate::crawler::Crawler;
-use crate::post::DailyPostDate;
-use crate::qbot::QBotApiClient;
+use crate::post::{DailyPost, DailyPostDate};
+use crate::qbot::model::Channel;
+use crate::qbot::{ChannelId, GuildId, QBotApiClient};
 
-fn process_html(html: &str) -> Result<String, &'static str> {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableMode {
+    /// Flatten each row into a paragraph with cells separated by ` | `.
+    #[default]
+    Text,
+    Placeholder,
+}
+
+impl FromStr for TableMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "placeholder" => Ok(Self::Placeholder),
+            _ => Err("Invalid table mode"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HtmlProcessConfig {
+    /// `<pre>` blocks with fewer characters than this are kept as is instead of being replaced
+    /// with a placeholder.
+    pub max_inline_code_chars: usize,
+    pub table_mode: TableMode,
+}
+
+fn text_content(node: &Node, output: &mut String) {
+    if let NodeData::Text { contents } = &node.data {
+        output.push_str(&contents.borrow());
+    }
+    for child in node.children.borrow().iter() {
+        text_content(child, output);
+    }
+}
+
+fn text_len(node: &Node) -> usize {
+    let own_len = match &node.data {
+        NodeData::Text { contents } => contents.borrow().chars().count(),
+        _ => 0,
+    };
+    own_len
+        + node
+            .children
+            .borrow()
+            .iter()
+            .map(|child| text_len(child))
+            .sum::<usize>()
+}
+
+fn is_element(node: &Node, local_name: &str) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name.local.eq_str_ignore_ascii_case(local_name))
+}
+
+fn table_rows(node: &Node, rows: &mut Vec<String>) {
+    if !is_element(node, "tr") {
+        for child in node.children.borrow().iter() {
+            table_rows(child, rows);
+        }
+        return;
+    }
+    let cells = node
+        .children
+        .borrow()
+        .iter()
+        .filter(|cell| is_element(cell, "td") || is_element(cell, "th"))
+        .map(|cell| {
+            let mut text = String::new();
+            text_content(cell, &mut text);
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect::<Vec<_>>();
+    if !cells.is_empty() {
+        rows.push(cells.join(" | "));
+    }
+}
+
+pub(super) fn process_html(html: &str, config: &HtmlProcessConfig) -> Result<String, &'static str> {
     let dom = parse_fragment(
         RcDom::default(),
         Default::default(),
@@ -40,19 +120,66 @@ fn process_html(html: &str) -> Result<String, &'static str> {
                 }));
             *node = el_data;
         }
-        fn process_elements(nodes: &mut [Rc<Node>]) {
+        fn replace_with_div_paragraphs(node: &mut Rc<Node>, paragraphs: &[String]) {
+            let el_data = Node::new(NodeData::Element {
+                name: QualName::new(None, ns!(), local_name!("div")),
+                attrs: Default::default(),
+                template_contents: Default::default(),
+                mathml_annotation_xml_integration_point: false,
+            });
+            for paragraph in paragraphs {
+                let p = Node::new(NodeData::Element {
+                    name: QualName::new(None, ns!(), local_name!("p")),
+                    attrs: Default::default(),
+                    template_contents: Default::default(),
+                    mathml_annotation_xml_integration_point: false,
+                });
+                p.children.borrow_mut().push(Node::new(NodeData::Text {
+                    contents: RefCell::new(paragraph.as_str().into()),
+                }));
+                el_data.children.borrow_mut().push(p);
+            }
+            *node = el_data;
+        }
+        fn process_elements(nodes: &mut [Rc<Node>], config: &HtmlProcessConfig) {
             for child in nodes {
                 match &child.data {
-                    NodeData::Element { name, .. }
+                    NodeData::Element { name, attrs, .. }
                         if name.local.eq_str_ignore_ascii_case("img") =>
                     {
-                        replace_with_div_text(child, "（此处应有图片，请前往原文链接查看）");
+                        let description = attrs
+                            .borrow()
+                            .iter()
+                            .filter(|attr| matches!(&*attr.name.local, "alt" | "title"))
+                            .map(|attr| attr.value.trim().to_string())
+                            .find(|value| !value.is_empty());
+                        let text = match description {
+                            Some(description) => {
+                                format!("（图片：{description}，请前往原文链接查看）")
+                            }
+                            None => "（此处应有图片，请前往原文链接查看）".into(),
+                        };
+                        replace_with_div_text(child, &text);
                     }
                     NodeData::Element { name, .. }
-                        if name.local.eq_str_ignore_ascii_case("pre") =>
+                        if name.local.eq_str_ignore_ascii_case("pre")
+                            && text_len(child) >= config.max_inline_code_chars =>
                     {
                         replace_with_div_text(child, "（此处应有代码块，请前往原文链接查看）");
                     }
+                    NodeData::Element { name, .. }
+                        if name.local.eq_str_ignore_ascii_case("table") =>
+                    {
+                        let mut rows = vec![];
+                        if config.table_mode == TableMode::Text {
+                            table_rows(child, &mut rows);
+                        }
+                        if rows.is_empty() {
+                            replace_with_div_text(child, "（此处有表格，请前往原文链接查看）");
+                        } else {
+                            replace_with_div_paragraphs(child, &rows);
+                        }
+                    }
                     NodeData::Element { name, attrs, .. }
                         if name.local.eq_str_ignore_ascii_case("a") =>
                     {
@@ -60,10 +187,10 @@ fn process_html(html: &str) -> Result<String, &'static str> {
                     }
                     _ => {}
                 }
-                process_elements(&mut *child.children.borrow_mut());
+                process_elements(&mut child.children.borrow_mut(), config);
             }
         }
-        process_elements(&mut *children);
+        process_elements(&mut children, config);
     }
     let mut output = Vec::with_capacity(html.len());
     for child in dom.document.children.borrow_mut().drain(..) {
@@ -74,61 +201,436 @@ fn process_html(html: &str) -> Result<String, &'static str> {
     String::from_utf8(output).or_else(|e| Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()))
 }
 
+/// Threads a post was sent as, kept so that 更新 can replace them.
+#[derive(Debug, Clone)]
+pub(super) struct SentThreads {
+    pub href: String,
+    pub title: String,
+    /// Title of the threads, by which 更新 finds them as QQ doesn't return their ids.
+    pub thread_title: String,
+    /// `(channel_id, task_id)` pairs, with the ids of the tasks that created the threads.
+    pub threads: Vec<(ChannelId, String)>,
+}
+
+pub(super) struct SendingGuard<'a> {
+    sending: &'a Mutex<BTreeSet<DailyPostDate>>,
+    date: DailyPostDate,
+}
+
+impl<'a> SendingGuard<'a> {
+    pub(super) fn acquire(
+        sending: &'a Mutex<BTreeSet<DailyPostDate>>,
+        date: DailyPostDate,
+    ) -> Option<Self> {
+        let inserted = sending.lock().unwrap().insert(date);
+        inserted.then(|| Self { sending, date })
+    }
+}
+
+impl Drop for SendingGuard<'_> {
+    fn drop(&mut self) {
+        self.sending.lock().unwrap().remove(&self.date);
+    }
+}
+
 impl<A: QBotApiClient + Sync, C: Crawler + Sync> ControllerImpl<A, C> {
-    pub(super) fn 发送<'a>(
-        &'a self,
-        _channel_id: &'a str,
+    pub(super) async fn 发送(
+        &self,
+        guild_id: &GuildId,
+        _channel_id: &ChannelId,
         date: DailyPostDate,
-    ) -> impl Future<Output = String> + Send + 'a {
-        async move {
-            let post_channel_id = &*self.news_channel_id;
-            let Some(post) = self.posts.lock().unwrap().get(&date).cloned() else {
-                return format!("没有找到 {} 的日报", date);
-            };
+        target_channel_ids: &[ChannelId],
+    ) -> String {
+        let target_channel_ids: Vec<_> = if target_channel_ids.is_empty() {
+            std::iter::once(self.news_channel_id_for(guild_id))
+                .chain(&self.mirror_channel_ids)
+                .collect()
+        } else {
+            if let Err(reply) = self
+                .check_channels_in_guild(guild_id, target_channel_ids)
+                .await
+            {
+                return reply;
+            }
+            target_channel_ids.iter().collect()
+        };
+        if !self.dry_run {
+            if let Err(reply) = self.check_forum_channels(&target_channel_ids).await {
+                return reply;
+            }
+        }
+        let Some(_guard) = SendingGuard::acquire(&self.sending, date) else {
+            return format!("{} 的日报正在发送中", date);
+        };
+        // Claim the post before awaiting so that it cannot be sent twice.
+        let Some(post) = self.posts.lock().unwrap().remove(&date) else {
+            return format!("没有找到 {} 的日报", date);
+        };
 
-            let title = format!("[{}] {}", post.date, post.title);
-            let mut content_html = &post.content_html;
-            let processed_html = process_html(content_html);
-            let mut process_error = String::new();
-            content_html = match &processed_html {
-                Ok(html) => html,
-                Err(e) => {
-                    warn!("Failed to process HTML: {}", e);
-                    process_error = format!(" （HTML 处理失败:{e}）");
-                    content_html
-                }
-            };
-            let html = format!(
-                r#"<p>{} 发表于 {}</p><p><a href="https://rustcc.cn{}">原文链接</a></p>{}"#,
-                post.author, post.publish_time, post.href, content_html
+        let (title, html, process_error) = self.render_post(&post);
+        if self.dry_run {
+            info!(
+                channel_ids = ?target_channel_ids,
+                title = %title,
+                html = %html,
+                "dry run, not sending thread"
+            );
+            let res = format!(
+                "发送成功（演练）: {} - {}{process_error}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
             );
+            self.restore_post(post);
+            return res;
+        }
+        let mut results = Vec::with_capacity(target_channel_ids.len());
+        for channel_id in target_channel_ids {
             let res = self
                 .api_client
-                .send_channel_thread_html(&post_channel_id, &title, &html)
+                .send_channel_thread_html(channel_id, &title, &html)
                 .await;
-            match res {
-                Ok(_) => {
-                    self.posts.lock().unwrap().remove(&date);
-                    format!(
-                        "发送成功: {} - {}{process_error}",
-                        post.date,
-                        sanitize_message(post.title)
-                    )
-                }
-                Err(e) => format!("发送失败: {}", sanitize_message(e.to_string())),
+            match &res {
+                Ok(_) if self.send_summary => self.send_summary(channel_id, &title, &post).await,
+                Ok(_) => {}
+                Err(e) => self.record_api_error("发送", e),
             }
+            results.push((channel_id, res));
+        }
+        self.record_sent_threads(
+            post.date,
+            &post,
+            results.iter().filter_map(|(channel_id, res)| {
+                Some(((*channel_id).clone(), res.as_ref().ok()?.clone()))
+            }),
+        );
+        if results.iter().any(|(_, res)| res.is_ok()) {
+            *self.last_sent.lock().unwrap() = Some(post.clone());
         }
+        if results.iter().all(|(_, res)| res.is_ok()) {
+            return format!(
+                "发送成功: {} - {}{process_error}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
+            );
+        }
+        // Keep the post so that the failed channels can be retried with an explicit target list.
+        let res = if let [(_, Err(e))] = &*results {
+            format!("发送失败: {}", self.sanitizer.sanitize(&e.to_string()))
+        } else {
+            let lines = results
+                .iter()
+                .map(|(channel_id, res)| match res {
+                    Ok(_) => format!("{channel_id} -> 成功"),
+                    Err(e) => format!("{channel_id} -> 失败: {e}"),
+                })
+                .collect::<Vec<_>>();
+            self.sanitizer.sanitize(&format!(
+                "部分频道发送失败: {} - {}\n{}",
+                post.date,
+                post.title,
+                lines.join("\n")
+            ))
+        };
+        self.restore_post(post);
+        res
+    }
+
+    /// Failures are only logged, since the thread itself has been sent already.
+    async fn send_summary(&self, channel_id: &ChannelId, title: &str, post: &DailyPost) {
+        let content = format!(
+            "{title}\n已发帖，原文链接: {}{}",
+            self.site_base_url, post.href
+        );
+        if let Err(e) = self
+            .api_client
+            .send_text_to_channel(channel_id, &content)
+            .await
+        {
+            warn!(%channel_id, "failed to send summary message: {:?}", e);
+            self.record_api_error("发送", &e);
+        }
+    }
+
+    async fn check_channels_in_guild(
+        &self,
+        guild_id: &GuildId,
+        channel_ids: &[ChannelId],
+    ) -> Result<(), String> {
+        let channels = match self.api_client.list_channels(guild_id).await {
+            Ok(channels) => channels,
+            Err(e) => {
+                self.record_api_error("发送", &e);
+                return Err(format!(
+                    "获取频道列表失败: {}",
+                    self.sanitizer.sanitize(&e.to_string())
+                ));
+            }
+        };
+        let foreign = channel_ids
+            .iter()
+            .filter(|id| !channels.iter().any(|channel| channel.id == **id))
+            .map(|id| id.as_str())
+            .collect::<Vec<_>>();
+        if foreign.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("以下频道不属于当前服务器: {}", foreign.join("、")))
+        }
+    }
+
+    pub(super) async fn check_forum_channels(
+        &self,
+        channel_ids: &[&ChannelId],
+    ) -> Result<(), String> {
+        for &channel_id in channel_ids {
+            let cached_type = self.channel_types.lock().unwrap().get(channel_id).copied();
+            let channel_type = match cached_type {
+                Some(channel_type) => channel_type,
+                None => match self.api_client.get_channel(channel_id).await {
+                    Ok(channel) => {
+                        self.channel_types
+                            .lock()
+                            .unwrap()
+                            .insert(channel.id, channel.channel_type);
+                        channel.channel_type
+                    }
+                    Err(e) => {
+                        self.record_api_error("发送", &e);
+                        return Err(format!(
+                            "获取频道信息失败: {}",
+                            self.sanitizer.sanitize(&e.to_string())
+                        ));
+                    }
+                },
+            };
+            if channel_type != Channel::TYPE_FORUM {
+                return Err(format!("目标频道不支持发帖: {channel_id}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the thread title, the thread HTML and a note on HTML processing errors.
+    pub(super) fn render_post(&self, post: &DailyPost) -> (String, String, String) {
+        let title = self.formatter.title(post);
+        let mut content_html = &post.content_html;
+        let processed_html = process_html(content_html, &self.html_config);
+        let mut process_error = String::new();
+        content_html = match &processed_html {
+            Ok(html) => html,
+            Err(e) => {
+                warn!("Failed to process HTML: {}", e);
+                process_error = format!(" （HTML 处理失败:{e}）");
+                content_html
+            }
+        };
+        let html = self
+            .formatter
+            .format_html(post, content_html, &self.site_base_url);
+        (title, html, process_error)
+    }
+
+    /// Remembers the threads the post was sent as, replacing earlier ones in the same channels.
+    pub(super) fn record_sent_threads(
+        &self,
+        date: DailyPostDate,
+        post: &DailyPost,
+        threads: impl IntoIterator<Item = (ChannelId, String)>,
+    ) {
+        let mut sent_threads = self.sent_threads.lock().unwrap();
+        let sent = sent_threads.entry(date).or_insert_with(|| SentThreads {
+            href: post.href.clone(),
+            title: post.title.clone(),
+            thread_title: String::new(),
+            threads: vec![],
+        });
+        sent.href.clone_from(&post.href);
+        sent.title.clone_from(&post.title);
+        sent.thread_title = self.formatter.title(post);
+        for (channel_id, task_id) in threads {
+            sent.threads
+                .retain(|(sent_channel_id, _)| *sent_channel_id != channel_id);
+            sent.threads.push((channel_id, task_id));
+        }
+    }
+
+    fn restore_post(&self, post: DailyPost) {
+        // Keep the newer post if the date was re-crawled in the meantime.
+        self.posts.lock().unwrap().entry(post.date).or_insert(post);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::controller::PostFormatter;
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::model::{Channel, Role, Thread, User};
+    use crate::qbot::{
+        InMemoryApiClient, MessageId, MockAuthorizer, QBotApiClientImpl, QBotApiError,
+        QBotApiResult, SentMessage,
+    };
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
     use super::*;
 
+    #[derive(Default)]
+    struct StallingApiClient {
+        thread_calls: AtomicUsize,
+        release: Notify,
+        fail: bool,
+    }
+
+    fn forum_channel(id: &str) -> Channel {
+        Channel {
+            id: id.into(),
+            guild_id: "guildId".into(),
+            name: id.into(),
+            channel_type: Channel::TYPE_FORUM,
+        }
+    }
+
+    impl QBotApiClient for StallingApiClient {
+        async fn get_me(&self) -> QBotApiResult<User> {
+            unreachable!("get_me called")
+        }
+        async fn list_channels(&self, _guild_id: &GuildId) -> QBotApiResult<Vec<Channel>> {
+            unreachable!("list_channels called")
+        }
+        async fn get_channel(&self, channel_id: &ChannelId) -> QBotApiResult<Channel> {
+            Ok(forum_channel(channel_id))
+        }
+        async fn list_guild_roles(&self, _guild_id: &GuildId) -> QBotApiResult<Vec<Role>> {
+            unreachable!("list_guild_roles called")
+        }
+        async fn mute_member(
+            &self,
+            _guild_id: &GuildId,
+            _user_id: &str,
+            _mute_end_timestamp: u64,
+        ) -> QBotApiResult<()> {
+            unreachable!("mute_member called")
+        }
+        async fn unmute_member(&self, _guild_id: &GuildId, _user_id: &str) -> QBotApiResult<()> {
+            unreachable!("unmute_member called")
+        }
+        async fn reply_text_to_channel_message(
+            &self,
+            _message_id: &MessageId,
+            _msg_seq: u32,
+            _channel_id: &ChannelId,
+            _content: &str,
+        ) -> QBotApiResult<()> {
+            unreachable!("reply_text_to_channel_message called")
+        }
+        async fn send_channel_thread_html(
+            &self,
+            _channel_id: &ChannelId,
+            _title: &str,
+            _html: &str,
+        ) -> QBotApiResult<String> {
+            self.thread_calls.fetch_add(1, Ordering::SeqCst);
+            self.release.notified().await;
+            if self.fail {
+                return Err(QBotApiError::ApiError {
+                    status_code: 500,
+                    code: 0,
+                    message: "boom".into(),
+                    trace_id: "".into(),
+                });
+            }
+            Ok("threadId".into())
+        }
+        async fn list_threads(&self, _channel_id: &ChannelId) -> QBotApiResult<Vec<Thread>> {
+            unreachable!("list_threads called")
+        }
+        async fn delete_thread(
+            &self,
+            _channel_id: &ChannelId,
+            _thread_id: &str,
+        ) -> QBotApiResult<()> {
+            unreachable!("delete_thread called")
+        }
+        async fn send_text_to_channel(
+            &self,
+            _channel_id: &ChannelId,
+            _content: &str,
+        ) -> QBotApiResult<()> {
+            unreachable!("send_text_to_channel called")
+        }
+    }
+
+    /// An [`InMemoryApiClient`] with forum channels of the given ids in `guildId`.
+    fn api_client(channel_ids: &[&str]) -> InMemoryApiClient {
+        let api_client = InMemoryApiClient::new();
+        for &id in channel_ids {
+            api_client.add_channel(forum_channel(id));
+        }
+        api_client
+    }
+
+    fn post(date: DailyPostDate) -> DailyPost {
+        DailyPost {
+            href: "/article?id=1".into(),
+            content_html: "<p>内容</p>".into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-13 16:16".into(),
+            published_at: None,
+            date,
+            tags: vec![],
+            content_hash: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_skips_sending() {
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            true,
+        );
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        assert_eq!(res, "发送成功（演练）: 2024-04-11 - TinyUFO");
+        assert!(controller.posts.lock().unwrap().contains_key(&date));
+        assert!(controller.api_client.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_title_mentions_escaped() {
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            true,
+        );
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        let post = DailyPost {
+            title: "@here TinyUFO".into(),
+            ..post(date)
+        };
+        controller.posts.lock().unwrap().insert(date, post);
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        assert_eq!(res, "发送成功（演练）: 2024-04-11 - @\u{200b}here TinyUFO");
+        assert!(controller.api_client.sent().is_empty());
+    }
+
     #[test]
     fn test_html_replacement() {
         let res = process_html(
             r#"<div>内容：<a rel="relval"><img src="aa"></a><pre></pre></div><div>div2</div>"#,
+            &HtmlProcessConfig::default(),
         );
         let res = res.unwrap();
         println!("{res}");
@@ -149,4 +651,423 @@ mod tests {
             (true, true)
         );
     }
+
+    #[test]
+    fn test_image_alt_text() {
+        let config = HtmlProcessConfig::default();
+        let res = process_html(r#"<p><img src="a.png" alt="架构图"></p>"#, &config).unwrap();
+        assert_eq!(
+            res,
+            "<p><div>（图片：架构图，请前往原文链接查看）</div></p>"
+        );
+
+        let res = process_html(r#"<img src="a.png" alt="" title="流程">"#, &config).unwrap();
+        assert_eq!(res, "<div>（图片：流程，请前往原文链接查看）</div>");
+    }
+
+    #[test]
+    fn test_table_as_text() {
+        let html = include_str!("../../tests/fixtures/rustcc_post_table.html");
+        let res = process_html(html, &HtmlProcessConfig::default()).unwrap();
+        assert!(!res.contains("<table"));
+        assert!(
+            res.contains("<div><p>库 | 耗时</p><p>TinyUFO | 1.2 ms</p><p>moka | 2.5 ms</p></div>")
+        );
+    }
+
+    #[test]
+    fn test_table_placeholder() {
+        let html = include_str!("../../tests/fixtures/rustcc_post_table.html");
+        let config = HtmlProcessConfig {
+            table_mode: TableMode::Placeholder,
+            ..Default::default()
+        };
+        let res = process_html(html, &config).unwrap();
+        assert!(!res.contains("<table"));
+        assert!(!res.contains("TinyUFO"));
+        assert!(res.contains("<div>（此处有表格，请前往原文链接查看）</div>"));
+    }
+
+    #[test]
+    fn test_short_code_block_kept() {
+        let config = HtmlProcessConfig {
+            max_inline_code_chars: 20,
+            ..Default::default()
+        };
+        let res = process_html(r#"<pre><code>let a = 1 < 2;</code></pre>"#, &config).unwrap();
+        assert_eq!(res, "<pre><code>let a = 1 &lt; 2;</code></pre>");
+
+        let res = process_html(
+            r#"<pre><code>fn main() { println!("hello world"); }</code></pre>"#,
+            &config,
+        )
+        .unwrap();
+        assert!(!res.contains("<pre"));
+        assert!(res.contains("（此处应有代码块，请前往原文链接查看）"));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_send_for_same_date() {
+        let controller = ControllerImpl::new(
+            StallingApiClient::default(),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        );
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+        let (guild_id, channel_id) = (GuildId::from("guildId"), ChannelId::from("channelId"));
+
+        let first = controller.发送(&guild_id, &channel_id, date, &[]);
+        let second = async {
+            tokio::task::yield_now().await;
+            let res = controller.发送(&guild_id, &channel_id, date, &[]).await;
+            controller.api_client.release.notify_one();
+            res
+        };
+        let (first, second) = tokio::join!(first, second);
+        assert_eq!(first, "发送成功: 2024-04-11 - TinyUFO");
+        assert_eq!(second, "2024-04-11 的日报正在发送中");
+        assert_eq!(controller.api_client.thread_calls.load(Ordering::SeqCst), 1);
+
+        let res = controller.发送(&guild_id, &channel_id, date, &[]).await;
+        assert_eq!(res, "没有找到 2024-04-11 的日报");
+    }
+
+    #[tokio::test]
+    async fn test_post_claimed_while_sending() {
+        let controller = ControllerImpl::new(
+            StallingApiClient {
+                fail: true,
+                ..Default::default()
+            },
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        );
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+        let (guild_id, channel_id) = (GuildId::from("guildId"), ChannelId::from("channelId"));
+
+        let send = controller.发送(&guild_id, &channel_id, date, &[]);
+        let check = async {
+            tokio::task::yield_now().await;
+            let claimed = !controller.posts.lock().unwrap().contains_key(&date);
+            controller.api_client.release.notify_one();
+            claimed
+        };
+        let (res, claimed) = tokio::join!(send, check);
+        assert!(claimed);
+        assert!(res.starts_with("发送失败: "), "{res}");
+        assert!(controller.posts.lock().unwrap().contains_key(&date));
+    }
+
+    #[tokio::test]
+    async fn test_custom_formatter() {
+        struct CustomFormatter;
+        impl PostFormatter for CustomFormatter {
+            fn header(&self, post: &DailyPost) -> String {
+                format!("<p>作者：{}</p>", post.author)
+            }
+            fn include_original_link(&self) -> bool {
+                false
+            }
+        }
+
+        let controller = ControllerImpl::new(
+            api_client(&["newsChannelId"]),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_formatter(CustomFormatter);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        assert_eq!(res, "发送成功: 2024-04-11 - TinyUFO");
+        assert_eq!(
+            controller.api_client.threads(),
+            [(
+                "newsChannelId".into(),
+                "[2024-04-11] TinyUFO".to_string(),
+                "<p>作者：PsiACE</p><p>内容</p>".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reading_time_in_header() {
+        struct ReadingTimeFormatter;
+        impl PostFormatter for ReadingTimeFormatter {
+            fn include_reading_time(&self) -> bool {
+                true
+            }
+        }
+
+        let controller = ControllerImpl::new(
+            api_client(&["newsChannelId"]),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_formatter(ReadingTimeFormatter);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        let threads = controller.api_client.threads();
+        assert!(threads[0]
+            .2
+            .starts_with("<p>PsiACE 发表于 2024-04-13 16:16</p><p>约 2 字，阅读约 1 分钟</p>"));
+    }
+
+    #[tokio::test]
+    async fn test_original_link_uses_site_base_url() {
+        let controller = ControllerImpl::new(
+            api_client(&["newsChannelId"]),
+            CrawlerImpl::new("http://sandbox.test".into()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_site_base_url("http://sandbox.test");
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        let threads = controller.api_client.threads();
+        assert!(threads[0]
+            .2
+            .contains(r#"<a href="http://sandbox.test/article?id=1">原文链接</a>"#));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_multiple_channels() {
+        let mut mock_server = mockito::Server::new_async().await;
+        for channel_id in ["newsChannelId", "mirrorChannelId"] {
+            mock_server
+                .mock("GET", &*format!("/channels/{channel_id}"))
+                .with_header("content-type", "application/json")
+                .with_body(
+                    json!({
+                        "id": channel_id,
+                        "guild_id": "guildId",
+                        "name": channel_id,
+                        "type": 10007,
+                    })
+                    .to_string(),
+                )
+                .create_async()
+                .await;
+        }
+        let mock_ok = mock_server
+            .mock("PUT", "/channels/newsChannelId/threads")
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "task_id": "1", "create_time": "0" }).to_string())
+            .create_async()
+            .await;
+        let mock_failed = mock_server
+            .mock("PUT", "/channels/mirrorChannelId/threads")
+            .with_status(500)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 500, "message": "internal error" }).to_string())
+            // The first attempt and the default two retries.
+            .expect(3)
+            .create_async()
+            .await;
+        let controller = ControllerImpl::new(
+            QBotApiClientImpl::new(
+                mock_server.url(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            CrawlerImpl::new(mock_server.url()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_mirror_channel_ids(vec!["mirrorChannelId".into()]);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        let mut lines = res.lines();
+        assert_eq!(lines.next(), Some("部分频道发送失败: 2024-04-11 - TinyUFO"));
+        assert_eq!(lines.next(), Some("newsChannelId -> 成功"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("mirrorChannelId -> 失败: HTTP 500"));
+        assert!(controller.posts.lock().unwrap().contains_key(&date));
+        mock_ok.assert_async().await;
+        mock_failed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_news_channel_per_guild() {
+        let controller = ControllerImpl::new(
+            api_client(&["newsChannelId", "otherNewsChannelId"]),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_guild_news_channel_ids(HashMap::from([(
+            "otherGuildId".into(),
+            "otherNewsChannelId".into(),
+        )]));
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+
+        for guild_id in ["otherGuildId", "guildId"] {
+            controller.posts.lock().unwrap().insert(date, post(date));
+            let res = controller
+                .发送(&guild_id.into(), &"channelId".into(), date, &[])
+                .await;
+            assert_eq!(res, "发送成功: 2024-04-11 - TinyUFO");
+        }
+        let channel_ids = controller
+            .api_client
+            .threads()
+            .into_iter()
+            .map(|(channel_id, _, _)| channel_id)
+            .collect::<Vec<_>>();
+        assert_eq!(channel_ids, ["otherNewsChannelId", "newsChannelId"]);
+    }
+
+    #[tokio::test]
+    async fn test_target_channels_must_belong_to_guild() {
+        let channel = |id: &str, guild_id: &str| Channel {
+            guild_id: guild_id.into(),
+            ..forum_channel(id)
+        };
+        let api_client = InMemoryApiClient::new();
+        api_client.add_channel(channel("1", "guildId"));
+        api_client.add_channel(channel("2", "otherGuildId"));
+        let controller = ControllerImpl::new(
+            api_client,
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        );
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        let res = controller
+            .发送(
+                &"guildId".into(),
+                &"channelId".into(),
+                date,
+                &["1".into(), "2".into(), "3".into()],
+            )
+            .await;
+        assert_eq!(res, "以下频道不属于当前服务器: 2、3");
+        assert!(controller.api_client.threads().is_empty());
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &["1".into()])
+            .await;
+        assert_eq!(res, "发送成功: 2024-04-11 - TinyUFO");
+        assert_eq!(controller.api_client.threads()[0].0, "1");
+    }
+
+    #[tokio::test]
+    async fn test_target_channel_must_be_forum() {
+        let mut mock_server = mockito::Server::new_async().await;
+        let mut mock_channels = vec![];
+        for (channel_id, channel_type) in [("forumChannelId", 10007), ("textChannelId", 0)] {
+            let mock = mock_server
+                .mock("GET", &*format!("/channels/{channel_id}"))
+                .with_header("content-type", "application/json")
+                .with_body(
+                    json!({
+                        "id": channel_id,
+                        "guild_id": "guildId",
+                        "name": channel_id,
+                        "type": channel_type,
+                    })
+                    .to_string(),
+                )
+                .expect(1)
+                .create_async()
+                .await;
+            mock_channels.push(mock);
+        }
+        let mock_thread = mock_server
+            .mock("PUT", "/channels/forumChannelId/threads")
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "task_id": "1", "create_time": "0" }).to_string())
+            .expect(2)
+            .create_async()
+            .await;
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+
+        for news_channel_id in ["forumChannelId", "textChannelId"] {
+            let controller = ControllerImpl::new(
+                QBotApiClientImpl::new(
+                    mock_server.url(),
+                    "appId",
+                    MockAuthorizer("accessToken".into()),
+                ),
+                CrawlerImpl::new(mock_server.url()),
+                news_channel_id.into(),
+                false,
+            );
+            for _ in 0..2 {
+                controller.posts.lock().unwrap().insert(date, post(date));
+                let res = controller
+                    .发送(&"guildId".into(), &"channelId".into(), date, &[])
+                    .await;
+                if news_channel_id == "forumChannelId" {
+                    assert_eq!(res, "发送成功: 2024-04-11 - TinyUFO");
+                } else {
+                    assert_eq!(res, "目标频道不支持发帖: textChannelId");
+                }
+            }
+        }
+        mock_thread.assert_async().await;
+        for mock in mock_channels {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_summary_message() {
+        let controller = ControllerImpl::new(
+            api_client(&["newsChannelId"]),
+            CrawlerImpl::new("http://localhost".into()),
+            "newsChannelId".into(),
+            false,
+        )
+        .with_site_base_url("https://rustcc.cn")
+        .with_summary_message(true);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller.posts.lock().unwrap().insert(date, post(date));
+
+        let res = controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        assert_eq!(res, "发送成功: 2024-04-11 - TinyUFO");
+        let sent = controller.api_client.sent();
+        assert_eq!(sent.len(), 2);
+        let SentMessage::Thread {
+            channel_id, title, ..
+        } = &sent[0]
+        else {
+            panic!("expected a thread, got {:?}", sent[0]);
+        };
+        assert_eq!(channel_id, "newsChannelId");
+        assert_eq!(
+            sent[1],
+            SentMessage::Text {
+                channel_id: "newsChannelId".into(),
+                content: format!("{title}\n已发帖，原文链接: https://rustcc.cn/article?id=1"),
+            }
+        );
+    }
 }
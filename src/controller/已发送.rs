@@ -0,0 +1,76 @@
+use super::ControllerImpl;
+
+impl<A, C> ControllerImpl<A, C> {
+    pub(super) async fn 已发送(&self) -> String {
+        let sent_threads = self.sent_threads.lock().unwrap();
+        if sent_threads.is_empty() {
+            return "还没有发送过日报".into();
+        }
+        let lines = sent_threads
+            .iter()
+            .map(|(date, sent)| {
+                let threads = sent
+                    .threads
+                    .iter()
+                    .map(|(channel_id, thread_id)| format!("{channel_id} -> {thread_id}"))
+                    .collect::<Vec<_>>();
+                format!("{date} - {}\n{}", sent.title, threads.join("\n"))
+            })
+            .collect::<Vec<_>>();
+        self.sanitizer.sanitize(&lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::InMemoryCrawler;
+    use crate::post::{DailyPost, DailyPostDate};
+    use crate::qbot::model::Channel;
+    use crate::qbot::InMemoryApiClient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_task_id_stored_after_send() {
+        let api_client = InMemoryApiClient::new();
+        api_client.add_channel(Channel {
+            id: "newsChannelId".into(),
+            guild_id: "guildId".into(),
+            name: "日报".into(),
+            channel_type: Channel::TYPE_FORUM,
+        });
+        let controller = ControllerImpl::new(
+            api_client,
+            InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        assert_eq!(controller.已发送().await, "还没有发送过日报");
+
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        let post = DailyPost {
+            href: "/article?id=1".into(),
+            content_html: "<p>内容</p>".into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-11 16:16".into(),
+            published_at: None,
+            date,
+            tags: vec![],
+            content_hash: 0,
+        };
+        controller.posts.lock().unwrap().insert(date, post);
+        controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+
+        assert_eq!(
+            controller.sent_threads.lock().unwrap()[&date].threads,
+            [("newsChannelId".into(), "task0".to_string())]
+        );
+        assert_eq!(
+            controller.已发送().await,
+            "2024-04-11 - TinyUFO\nnewsChannelId -> task0"
+        );
+    }
+}
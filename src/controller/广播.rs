@@ -0,0 +1,118 @@
+use tracing::info;
+
+use super::ControllerImpl;
+use crate::qbot::{GuildId, QBotApiClient};
+
+impl<A: QBotApiClient + Sync, C: Sync> ControllerImpl<A, C> {
+    pub(super) async fn 广播(&self, guild_id: &GuildId, content: &str) -> String {
+        if content.is_empty() {
+            return "请输入广播内容".into();
+        }
+        let channel_id = self.news_channel_id_for(guild_id);
+        if self.dry_run {
+            info!(%channel_id, content, "dry run, not broadcasting");
+            return "广播成功（演练）".into();
+        }
+        match self
+            .api_client
+            .send_text_to_channel(channel_id, content)
+            .await
+        {
+            Ok(()) => "广播成功".into(),
+            Err(e) => {
+                self.record_api_error("广播", &e);
+                format!("广播失败: {}", self.sanitizer.sanitize(&e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mockito::{Server, ServerGuard};
+    use serde_json::json;
+
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::{MockAuthorizer, QBotApiClientImpl};
+
+    use super::*;
+
+    fn controller(
+        mock_server: &ServerGuard,
+    ) -> ControllerImpl<QBotApiClientImpl<MockAuthorizer>, CrawlerImpl> {
+        ControllerImpl::new(
+            QBotApiClientImpl::new(
+                mock_server.url(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            CrawlerImpl::new(mock_server.url()),
+            "newsChannelId".into(),
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("POST", "/channels/newsChannelId/messages")
+            .match_body(mockito::Matcher::Json(json!({ "content": "公告" })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({}).to_string())
+            .create_async()
+            .await;
+        let res = controller(&mock_server)
+            .广播(&"guildId".into(), "公告")
+            .await;
+        assert_eq!(res, "广播成功");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_per_guild() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("POST", "/channels/otherNewsChannelId/messages")
+            .match_body(mockito::Matcher::Json(json!({ "content": "公告" })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({}).to_string())
+            .create_async()
+            .await;
+        let controller = controller(&mock_server).with_guild_news_channel_ids(HashMap::from([(
+            "otherGuildId".into(),
+            "otherNewsChannelId".into(),
+        )]));
+        let res = controller.广播(&"otherGuildId".into(), "公告").await;
+        assert_eq!(res, "广播成功");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_failed() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("POST", "/channels/newsChannelId/messages")
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 304003, "message": "no permission" }).to_string())
+            .create_async()
+            .await;
+        let controller = controller(&mock_server);
+        let res = controller.广播(&"guildId".into(), "公告").await;
+        assert!(
+            res.starts_with("广播失败: HTTP 403: 304003 no permission"),
+            "{res}"
+        );
+        assert!(controller.最近错误().await.contains("广播"));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_empty() {
+        let mock_server = Server::new_async().await;
+        let res = controller(&mock_server).广播(&"guildId".into(), "").await;
+        assert_eq!(res, "请输入广播内容");
+    }
+}
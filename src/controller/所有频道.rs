@@ -1,26 +1,91 @@
-use std::future::Future;
-
 use super::ControllerImpl;
-use crate::qbot::QBotApiClient;
+use crate::qbot::{GuildId, QBotApiClient};
 
 impl<A: QBotApiClient + Sync, C: Sync> ControllerImpl<A, C> {
-    pub(super) fn 所有频道<'a>(
-        &'a self,
-        guild_id: &'a str,
-    ) -> impl Future<Output = String> + Send + 'a {
-        async move {
-            let channels = match self.api_client.list_channels(guild_id).await {
-                Ok(channels) => channels,
-                Err(e) => {
-                    return format!("获取频道列表失败: {e}");
-                }
-            };
-            let channel_desc = channels
-                .into_iter()
-                .map(|c| format!("{} {}", c.id, c.name))
-                .collect::<Vec<_>>()
-                .join("; ");
-            channel_desc
+    pub(super) async fn 所有频道(&self, guild_id: &GuildId, keyword: &str) -> String {
+        let channels = match self.api_client.list_channels(guild_id).await {
+            Ok(channels) => channels,
+            Err(e) => {
+                self.record_api_error("所有频道", &e);
+                return format!("获取频道列表失败: {e}");
+            }
+        };
+        let keyword = keyword.to_lowercase();
+        let channel_lines = channels
+            .into_iter()
+            .filter(|c| c.name.to_lowercase().contains(&keyword))
+            .map(|c| format!("{} -> {}", c.name, c.id))
+            .collect::<Vec<_>>();
+        if channel_lines.is_empty() {
+            return "没有频道".into();
         }
+        format!(
+            "{}\n共 {} 个频道",
+            channel_lines.join("\n"),
+            channel_lines.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Server, ServerGuard};
+    use serde_json::json;
+
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::{MockAuthorizer, QBotApiClientImpl};
+
+    use super::*;
+
+    async fn controller_with_channels(
+        mock_server: &mut ServerGuard,
+    ) -> ControllerImpl<QBotApiClientImpl<MockAuthorizer>, CrawlerImpl> {
+        mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!([
+                    { "id": "1", "guild_id": "guildId", "name": "公告" },
+                    { "id": "2", "guild_id": "guildId", "name": "Rust日报" },
+                    { "id": "3", "guild_id": "guildId", "name": "rust 闲聊" },
+                ])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        ControllerImpl::new(
+            QBotApiClientImpl::new(
+                mock_server.url(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            CrawlerImpl::new(mock_server.url()),
+            "".into(),
+            false,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_list_all_channels() {
+        let mut mock_server = Server::new_async().await;
+        let controller = controller_with_channels(&mut mock_server).await;
+        let res = controller.所有频道(&"guildId".into(), "").await;
+        assert_eq!(res, "公告 -> 1\nRust日报 -> 2\nrust 闲聊 -> 3\n共 3 个频道");
+    }
+
+    #[tokio::test]
+    async fn test_list_channels_filtered() {
+        let mut mock_server = Server::new_async().await;
+        let controller = controller_with_channels(&mut mock_server).await;
+        let res = controller.所有频道(&"guildId".into(), "RUST").await;
+        assert_eq!(res, "Rust日报 -> 2\nrust 闲聊 -> 3\n共 2 个频道");
+    }
+
+    #[tokio::test]
+    async fn test_list_channels_none_matched() {
+        let mut mock_server = Server::new_async().await;
+        let controller = controller_with_channels(&mut mock_server).await;
+        let res = controller.所有频道(&"guildId".into(), "不存在").await;
+        assert_eq!(res, "没有频道");
     }
 }
@@ -0,0 +1,264 @@
+use super::发送::SendingGuard;
+use super::ControllerImpl;
+use crate::crawler::Crawler;
+use crate::post::DailyPostDate;
+use crate::qbot::{ChannelId, QBotApiClient, QBotApiResult};
+
+impl<A: QBotApiClient + Sync, C: Crawler + Sync> ControllerImpl<A, C> {
+    pub(super) async fn 更新(&self, date: DailyPostDate) -> String {
+        let Some(sent) = self.sent_threads.lock().unwrap().get(&date).cloned() else {
+            return format!("没有找到 {date} 已发送的帖子");
+        };
+        let Some(_guard) = SendingGuard::acquire(&self.sending, date) else {
+            return format!("{date} 的日报正在发送中");
+        };
+        let post = match self.crawler.fetch_post(&sent.href).await {
+            Ok(post) => post,
+            Err(e) => {
+                self.record_error("更新", &e, None);
+                return format!("爬取失败: {e}");
+            }
+        };
+
+        let (title, html, process_error) = self.render_post(&post);
+        let mut failures = vec![];
+        for (channel_id, _) in sent.threads {
+            if let Err(failure) = self
+                .delete_sent_threads(&channel_id, &sent.thread_title)
+                .await
+            {
+                failures.push(format!("{channel_id} -> {failure}"));
+                continue;
+            }
+            match self
+                .api_client
+                .send_channel_thread_html(&channel_id, &title, &html)
+                .await
+            {
+                Ok(task_id) => {
+                    self.record_sent_threads(date, &post, [(channel_id, task_id)]);
+                }
+                Err(e) => {
+                    self.record_api_error("更新", &e);
+                    failures.push(format!("{channel_id} -> 发送失败: {e}"));
+                    // The old thread is gone, so there is nothing left to replace.
+                    if let Some(sent) = self.sent_threads.lock().unwrap().get_mut(&date) {
+                        sent.threads.retain(|(id, _)| *id != channel_id);
+                    }
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            format!(
+                "更新成功: {date} - {}{process_error}",
+                self.sanitizer.sanitize(&post.title)
+            )
+        } else {
+            self.sanitizer.sanitize(&format!(
+                "更新失败: {date} - {}\n{}",
+                post.title,
+                failures.join("\n")
+            ))
+        }
+    }
+
+    /// Deletes the threads the bot sent to the channel under `thread_title`. Fails if there are
+    /// none, so that 更新 doesn't end up posting a duplicate next to one it couldn't find.
+    async fn delete_sent_threads(
+        &self,
+        channel_id: &ChannelId,
+        thread_title: &str,
+    ) -> Result<(), String> {
+        let thread_ids = match self.find_sent_threads(channel_id, thread_title).await {
+            Ok(thread_ids) if thread_ids.is_empty() => return Err("找不到已发送的帖子".into()),
+            Ok(thread_ids) => thread_ids,
+            Err(e) => {
+                self.record_api_error("更新", &e);
+                return Err(format!("查找帖子失败: {e}"));
+            }
+        };
+        for thread_id in thread_ids {
+            if let Err(e) = self.api_client.delete_thread(channel_id, &thread_id).await {
+                self.record_api_error("更新", &e);
+                return Err(format!("删除失败: {e}"));
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_sent_threads(
+        &self,
+        channel_id: &ChannelId,
+        thread_title: &str,
+    ) -> QBotApiResult<Vec<String>> {
+        let bot_id = self.api_client.get_me().await?.id;
+        let threads = self.api_client.list_threads(channel_id).await?;
+        Ok(threads
+            .into_iter()
+            .filter(|thread| thread.author_id == bot_id && thread.thread_info.title == thread_title)
+            .map(|thread| thread.thread_info.thread_id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::InMemoryCrawler;
+    use crate::post::DailyPost;
+    use crate::qbot::model::Channel;
+    use crate::qbot::{InMemoryApiClient, MockAuthorizer, QBotApiClientImpl, SentMessage};
+    use serde_json::json;
+
+    use super::*;
+
+    fn post(content: &str) -> DailyPost {
+        DailyPost {
+            href: "/article?id=1".into(),
+            content_html: format!("<p>{content}</p>"),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-11 16:16".into(),
+            published_at: None,
+            date: "2024-04-11".parse().unwrap(),
+            tags: vec![],
+            content_hash: 0,
+        }
+    }
+
+    fn controller(corrected: &str) -> ControllerImpl<InMemoryApiClient, InMemoryCrawler> {
+        let api_client = InMemoryApiClient::new();
+        api_client.add_channel(Channel {
+            id: "newsChannelId".into(),
+            guild_id: "guildId".into(),
+            name: "日报".into(),
+            channel_type: Channel::TYPE_FORUM,
+        });
+        let crawler = InMemoryCrawler::new(vec![post(corrected)]);
+        ControllerImpl::new(api_client, crawler, "newsChannelId".into(), false)
+    }
+
+    async fn send(controller: &ControllerImpl<InMemoryApiClient, InMemoryCrawler>) {
+        let post = post("错误");
+        controller.posts.lock().unwrap().insert(post.date, post);
+        let reply = controller
+            .发送(
+                &"guildId".into(),
+                &"channelId".into(),
+                "2024-04-11".parse().unwrap(),
+                &[],
+            )
+            .await;
+        assert_eq!(reply, "发送成功: 2024-04-11 - TinyUFO");
+    }
+
+    #[tokio::test]
+    async fn test_update_replaces_thread() {
+        let controller = controller("更正");
+        send(&controller).await;
+
+        let reply = controller.更新("2024-04-11".parse().unwrap()).await;
+        assert_eq!(reply, "更新成功: 2024-04-11 - TinyUFO");
+        let threads = controller.api_client.threads();
+        assert_eq!(threads.len(), 1);
+        assert!(threads[0].2.contains("更正"));
+        assert!(controller
+            .api_client
+            .sent()
+            .contains(&SentMessage::ThreadDeleted {
+                channel_id: "newsChannelId".into(),
+                thread_id: "thread0".into(),
+            }));
+
+        // The replacement can be updated again.
+        let reply = controller.更新("2024-04-11".parse().unwrap()).await;
+        assert_eq!(reply, "更新成功: 2024-04-11 - TinyUFO");
+        assert_eq!(controller.api_client.threads().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_thread_deleted_by_hand() {
+        let controller = controller("更正");
+        send(&controller).await;
+        controller
+            .api_client
+            .delete_thread(&"newsChannelId".into(), "thread0")
+            .await
+            .unwrap();
+
+        let reply = controller.更新("2024-04-11".parse().unwrap()).await;
+        assert_eq!(
+            reply,
+            "更新失败: 2024-04-11 - TinyUFO\nnewsChannelId -> 找不到已发送的帖子"
+        );
+        assert!(controller.api_client.threads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_delete_not_found() {
+        let mut mock_server = mockito::Server::new_async().await;
+        mock_server
+            .mock("GET", "/users/@me")
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "id": "botId", "username": "rust-cn-qbot" }).to_string())
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", "/channels/newsChannelId/threads")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "threads": [{
+                        "author_id": "botId",
+                        "thread_info": { "thread_id": "threadId", "title": "[2024-04-11] TinyUFO" },
+                    }],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        mock_server
+            .mock("DELETE", "/channels/newsChannelId/threads/threadId")
+            .with_status(404)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 11263, "message": "thread not found" }).to_string())
+            .create_async()
+            .await;
+        let mock_send = mock_server
+            .mock("PUT", "/channels/newsChannelId/threads")
+            .expect(0)
+            .create_async()
+            .await;
+        let controller = ControllerImpl::new(
+            QBotApiClientImpl::new(
+                mock_server.url(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            InMemoryCrawler::new(vec![post("更正")]),
+            "newsChannelId".into(),
+            false,
+        );
+        let date = "2024-04-11".parse().unwrap();
+        controller.record_sent_threads(
+            date,
+            &post("错误"),
+            [("newsChannelId".into(), "taskId".into())],
+        );
+
+        let reply = controller.更新(date).await;
+        assert!(
+            reply.starts_with("更新失败: 2024-04-11 - TinyUFO\nnewsChannelId -> 删除失败: "),
+            "{reply}"
+        );
+        mock_send.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_update_not_sent() {
+        let controller = controller("更正");
+        let reply = controller.更新("2024-04-11".parse().unwrap()).await;
+        assert_eq!(reply, "没有找到 2024-04-11 已发送的帖子");
+        assert!(controller.api_client.sent().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use super::ControllerImpl;
+use crate::crawler::Crawler;
+
+impl<A: Sync, C: Crawler + Sync> ControllerImpl<A, C> {
+    pub(super) async fn 最新列表(&self, n: usize) -> String {
+        let mut posts = match self.crawler.fetch_news_category().await {
+            Ok(category) => category.posts,
+            Err(e) => {
+                self.record_error("最新列表", &e, None);
+                return format!("获取列表失败: {}", e);
+            }
+        };
+        posts.sort_by_key(|post| std::cmp::Reverse(post.date));
+        let lines = posts
+            .iter()
+            .take(n)
+            .map(|post| format!("{} - {} {}", post.date, post.title, post.href))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return "没有找到文章".into();
+        }
+        self.sanitizer.sanitize(&lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Server;
+
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::InMemoryApiClient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_latest_list() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(
+                r#"<div class="article-list"><ul>
+                <li><a href="/article?id=11">【Rust日报】 2024-04-11 TinyUFO</a></li>
+                <li><a href="/article?id=13">【Rust日报】 2024-04-13 Rust 1-77</a></li>
+                <li><a href="/article?id=12">【Rust日报】 2024-04-12 C2PA</a></li>
+                </ul></div>"#,
+            )
+            .create_async()
+            .await;
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            CrawlerImpl::new(mock_server.url()),
+            "newsChannelId".into(),
+            false,
+        );
+
+        assert_eq!(
+            controller.最新列表(2).await,
+            "2024-04-13 - Rust 1-77 /article?id=13\n2024-04-12 - C2PA /article?id=12"
+        );
+        assert!(controller.posts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_latest_list_failure() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_status(503)
+            .create_async()
+            .await;
+        let controller = ControllerImpl::new(
+            InMemoryApiClient::new(),
+            CrawlerImpl::new(mock_server.url()),
+            "newsChannelId".into(),
+            false,
+        );
+
+        assert!(controller.最新列表(5).await.starts_with("获取列表失败: "));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Local};
+
+use super::ControllerImpl;
+use crate::qbot::QBotApiError;
+
+pub(super) struct LastError {
+    command: &'static str,
+    message: String,
+    trace_id: Option<String>,
+    at: DateTime<Local>,
+}
+
+impl<A, C> ControllerImpl<A, C> {
+    pub(super) fn record_error(
+        &self,
+        command: &'static str,
+        message: impl Display,
+        trace_id: Option<&str>,
+    ) {
+        *self.last_error.lock().unwrap() = Some(LastError {
+            command,
+            message: message.to_string(),
+            trace_id: trace_id.filter(|id| !id.is_empty()).map(Into::into),
+            at: Local::now(),
+        });
+    }
+
+    pub(super) fn record_api_error(&self, command: &'static str, error: &QBotApiError) {
+        let trace_id = match error {
+            QBotApiError::ApiError { trace_id, .. } => Some(&**trace_id),
+            QBotApiError::RequestError(_)
+            | QBotApiError::AccessTokenError(_)
+            | QBotApiError::InvalidAppId(_) => None,
+        };
+        self.record_error(command, error, trace_id);
+    }
+
+    pub(super) async fn 最近错误(&self) -> String {
+        let last_error = self.last_error.lock().unwrap();
+        let Some(last_error) = &*last_error else {
+            return "没有错误记录".into();
+        };
+        let mut reply = format!(
+            "{} {} 失败: {}",
+            last_error.at.format("%Y-%m-%d %H:%M:%S"),
+            last_error.command,
+            last_error.message
+        );
+        if let Some(trace_id) = &last_error.trace_id {
+            reply += &format!("\ntrace id: {trace_id}");
+        }
+        self.sanitizer.sanitize(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Server;
+    use serde_json::json;
+
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::{MockAuthorizer, QBotApiClientImpl};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_last_error() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .with_status(500)
+            .with_header("content-type", "application/json")
+            .with_header("X-Trace-Id", "traceId")
+            .with_body(json!({ "code": 500, "message": "internal error" }).to_string())
+            .create_async()
+            .await;
+        let controller = ControllerImpl::new(
+            QBotApiClientImpl::new(
+                mock_server.url(),
+                "appId",
+                MockAuthorizer("accessToken".into()),
+            ),
+            CrawlerImpl::new(mock_server.url()),
+            "".into(),
+            false,
+        );
+        assert_eq!(controller.最近错误().await, "没有错误记录");
+
+        controller.所有频道(&"guildId".into(), "").await;
+        let res = controller.最近错误().await;
+        let mut lines = res.lines();
+        assert!(
+            lines
+                .next()
+                .unwrap()
+                .ends_with(" 所有频道 失败: HTTP 500: 500 internal error (traceId)"),
+            "{res}"
+        );
+        assert_eq!(lines.next(), Some("trace id: traceId"));
+    }
+}
@@ -0,0 +1,50 @@
+use tracing::info;
+
+use super::ControllerImpl;
+
+impl<A, C> ControllerImpl<A, C> {
+    pub(super) async fn 清空(&self) -> String {
+        let cleared = std::mem::take(&mut *self.posts.lock().unwrap()).len();
+        info!(cleared, "cleared queued posts");
+        if cleared == 0 {
+            "没有待发送的文章".into()
+        } else {
+            format!("已清空 {cleared} 篇文章")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::CrawlerImpl;
+    use crate::post::{DailyPost, DailyPostDate};
+
+    use super::*;
+
+    fn post(date: &str) -> DailyPost {
+        DailyPost {
+            href: "/article?id=1".into(),
+            content_html: "<p>内容</p>".into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-13 16:16".into(),
+            published_at: None,
+            date: date.parse::<DailyPostDate>().unwrap(),
+            tags: vec![],
+            content_hash: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_posts() {
+        let controller = ControllerImpl::new((), CrawlerImpl::new("".into()), "".into(), false);
+        for date in ["2024-04-11", "2024-04-12"] {
+            let post = post(date);
+            controller.posts.lock().unwrap().insert(post.date, post);
+        }
+
+        assert_eq!(controller.清空().await, "已清空 2 篇文章");
+        assert!(controller.posts.lock().unwrap().is_empty());
+        assert_eq!(controller.清空().await, "没有待发送的文章");
+    }
+}
@@ -1,47 +1,171 @@
-use std::future::Future;
-
-use super::{sanitizer::sanitize_message, ControllerImpl};
+use super::ControllerImpl;
 use crate::crawler::Crawler;
+use crate::post::DailyPost;
 
 impl<A: Sync, C: Crawler + Sync> ControllerImpl<A, C> {
-    pub(super) fn 爬取<'a>(&'a self, href: &'a str) -> impl Future<Output = String> + Send + 'a {
-        async move {
-            if !href.starts_with('/') {
-                return "请输入合法的相对链接，以/开头，不包含域名".into();
+    pub(super) async fn 爬取(&self, href: &str) -> String {
+        if !href.starts_with('/') {
+            return "请输入合法的相对链接，以/开头，不包含域名".into();
+        }
+        match self.crawler.fetch_post(href).await {
+            Ok(post) => self.queue_post(post),
+            Err(e) => {
+                self.record_error("爬取", &e, None);
+                format!("爬取失败: {}", e)
             }
-            let post = match self.crawler.fetch_post(href).await {
-                Ok(post) => post,
-                Err(e) => {
-                    return format!("爬取失败: {}", e);
-                }
-            };
-
-            let mut gc_done_text = "";
-            {
-                let mut posts = self.posts.lock().unwrap();
-                if posts.len() > 20 {
-                    posts.clear();
-                    gc_done_text = "清理完成，";
-                }
+        }
+    }
+
+    pub(super) async fn 爬取最新(&self) -> String {
+        match self.crawler.fetch_latest_post().await {
+            Ok(post) => self.queue_post(post),
+            Err(e) => {
+                self.record_error("爬取最新", &e, None);
+                format!("爬取失败: {}", e)
             }
+        }
+    }
 
-            let old_post = {
-                let post = post.clone();
-                self.posts.lock().unwrap().insert(post.date, post)
-            };
-            if old_post.is_some() {
-                format!(
-                    "{gc_done_text}重新爬取成功: {} - {}",
-                    post.date,
-                    sanitize_message(post.title)
-                )
-            } else {
-                format!(
-                    "{gc_done_text}爬取成功: {} - {}",
-                    post.date,
-                    sanitize_message(post.title)
-                )
+    pub(super) fn queue_post(&self, post: DailyPost) -> String {
+        let mut gc_done_text = "";
+        {
+            let mut posts = self.posts.lock().unwrap();
+            if posts.len() > 20 {
+                posts.clear();
+                gc_done_text = "清理完成，";
             }
         }
+
+        let unchanged = self
+            .posts
+            .lock()
+            .unwrap()
+            .get(&post.date)
+            .is_some_and(|old_post| old_post.content_hash == post.content_hash);
+        if unchanged {
+            return format!(
+                "{gc_done_text}内容未变化: {} - {}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
+            );
+        }
+
+        let old_post = {
+            let post = post.clone();
+            self.posts.lock().unwrap().insert(post.date, post)
+        };
+        if old_post.is_some() {
+            format!(
+                "{gc_done_text}重新爬取成功: {} - {}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
+            )
+        } else {
+            format!(
+                "{gc_done_text}爬取成功: {} - {}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Server;
+
+    use crate::crawler::CrawlerImpl;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recrawl_reports_unchanged_content() {
+        let mut mock_server = Server::new_async().await;
+        let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+        let html = include_str!("../../tests/fixtures/rustcc_daily_post_article.html");
+        mock_server
+            .mock("GET", href)
+            .with_body(html)
+            .expect(2)
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", href)
+            .with_body(html.replacen("命中率", "命中次数", 1))
+            .create_async()
+            .await;
+        let controller =
+            ControllerImpl::new((), CrawlerImpl::new(mock_server.url()), "".into(), false);
+
+        let res = controller.爬取(href).await;
+        assert!(res.starts_with("爬取成功"), "{res}");
+        let res = controller.爬取(href).await;
+        assert!(res.starts_with("内容未变化"), "{res}");
+        let res = controller.爬取(href).await;
+        assert!(res.starts_with("重新爬取成功"), "{res}");
+    }
+
+    #[tokio::test]
+    async fn test_crawl_latest() {
+        let mut mock_server = Server::new_async().await;
+        let html = include_str!("../../tests/fixtures/rustcc_daily_post_article.html");
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(include_str!("../../tests/fixtures/rustcc_category.html"))
+            .expect(2)
+            .create_async()
+            .await;
+        let mock_post = mock_server
+            .mock("GET", "/article?id=8f907ec5-f15c-4651-9e75-58add3aaceb2")
+            .with_body(html)
+            .expect(2)
+            .create_async()
+            .await;
+        let controller =
+            ControllerImpl::new((), CrawlerImpl::new(mock_server.url()), "".into(), false);
+
+        let res = controller.爬取最新().await;
+        assert_eq!(res, "爬取成功: 2024-04-11 - TinyUFO - 无锁高性能缓存");
+        let res = controller.爬取最新().await;
+        assert!(res.starts_with("内容未变化"), "{res}");
+        mock_post.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_crawl_title_mentions_escaped() {
+        let mut mock_server = Server::new_async().await;
+        let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+        let html = include_str!("../../tests/fixtures/rustcc_daily_post_article.html").replace(
+            "TinyUFO - 无锁高性能缓存",
+            "@everyone &lt;@!123&gt; TinyUFO",
+        );
+        mock_server
+            .mock("GET", href)
+            .with_body(html)
+            .create_async()
+            .await;
+        let controller =
+            ControllerImpl::new((), CrawlerImpl::new(mock_server.url()), "".into(), false);
+
+        let res = controller.爬取(href).await;
+        assert_eq!(
+            res,
+            "爬取成功: 2024-04-11 - @\u{200b}everyone <\u{200b}@!123> TinyUFO"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_crawl_latest_failed() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_status(502)
+            .create_async()
+            .await;
+        let controller =
+            ControllerImpl::new((), CrawlerImpl::new(mock_server.url()), "".into(), false);
+
+        let res = controller.爬取最新().await;
+        assert_eq!(res, "爬取失败: unsuccessful HTTP status code: 502");
     }
 }
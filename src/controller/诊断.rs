@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::ControllerImpl;
+
+impl<A, C> ControllerImpl<A, C> {
+    pub(super) async fn 诊断(&self) -> String {
+        let diagnostics = self.ws_diagnostics.read().unwrap().clone();
+        if diagnostics.session_id.is_empty() {
+            return "WS 未连接".into();
+        }
+        let last_event = match diagnostics.last_event_at {
+            Some(at) => format!(
+                "{:?} 前",
+                Duration::from_secs((Instant::now() - at).as_secs())
+            ),
+            None => "无".into(),
+        };
+        format!(
+            "会话: {}\n最新序号: {}\n心跳间隔: {:?}\n上次事件: {}",
+            diagnostics.session_id,
+            diagnostics.last_seq,
+            diagnostics.heartbeat_interval,
+            last_event
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::CrawlerImpl;
+    use crate::qbot::ws::WsDiagnostics;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_diagnostics() {
+        let controller = ControllerImpl::new((), CrawlerImpl::new("".into()), "".into(), false);
+        assert_eq!(controller.诊断().await, "WS 未连接");
+
+        *controller.ws_diagnostics.write().unwrap() = WsDiagnostics {
+            session_id: "sessionId".into(),
+            last_seq: 42,
+            heartbeat_interval: Duration::from_secs(30),
+            last_event_at: Some(Instant::now()),
+        };
+        tokio::time::advance(Duration::from_millis(12500)).await;
+        assert_eq!(
+            controller.诊断().await,
+            "会话: sessionId\n最新序号: 42\n心跳间隔: 30s\n上次事件: 12s 前"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use super::{发送::process_html, ControllerImpl};
+use crate::post::DailyPostDate;
+
+impl<A, C> ControllerImpl<A, C> {
+    pub(super) async fn 详情(&self, date: DailyPostDate) -> String {
+        let Some(post) = self.posts.lock().unwrap().get(&date).cloned() else {
+            return format!("没有找到 {date} 的日报");
+        };
+        let tags = if post.tags.is_empty() {
+            "无".into()
+        } else {
+            post.tags.join("、")
+        };
+        let process_result = match process_html(&post.content_html, &self.html_config) {
+            Ok(html) => format!("成功，处理后 {} 字符", html.chars().count()),
+            Err(e) => format!("失败: {e}"),
+        };
+        self.sanitizer.sanitize(&format!(
+            "{date} - {}\n链接: {}\n作者: {}\n发布时间: {}\n标签: {tags}\n内容长度: {} 字符\nHTML 处理: {process_result}",
+            post.title,
+            post.href,
+            post.author,
+            post.publish_time,
+            post.content_html.chars().count(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::CrawlerImpl;
+    use crate::post::DailyPost;
+
+    use super::*;
+
+    fn post(date: DailyPostDate, content_html: &str) -> DailyPost {
+        DailyPost {
+            href: "/article?id=1".into(),
+            content_html: content_html.into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-13 16:16".into(),
+            published_at: None,
+            date,
+            tags: vec!["日报".into()],
+            content_hash: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_details() {
+        let controller = ControllerImpl::new((), CrawlerImpl::new("".into()), "".into(), false);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        controller
+            .posts
+            .lock()
+            .unwrap()
+            .insert(date, post(date, "<p>内容</p>"));
+
+        assert_eq!(
+            controller.详情(date).await,
+            "2024-04-11 - TinyUFO
+链接: /article?id=1
+作者: PsiACE
+发布时间: 2024-04-13 16:16
+标签: 日报
+内容长度: 9 字符
+HTML 处理: 成功，处理后 9 字符"
+        );
+        assert!(controller.posts.lock().unwrap().contains_key(&date));
+    }
+
+    #[tokio::test]
+    async fn test_details_not_found() {
+        let controller = ControllerImpl::new((), CrawlerImpl::new("".into()), "".into(), false);
+        assert_eq!(
+            controller.详情("2024-04-11".parse().unwrap()).await,
+            "没有找到 2024-04-11 的日报"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_details_report_processed_length() {
+        let controller = ControllerImpl::new((), CrawlerImpl::new("".into()), "".into(), false);
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        let content_html = format!("<pre>{}</pre>", "let x = 1;".repeat(100));
+        controller
+            .posts
+            .lock()
+            .unwrap()
+            .insert(date, post(date, &content_html));
+
+        let res = controller.详情(date).await;
+        assert!(res.contains("内容长度: 1011 字符"), "{res}");
+        assert!(res.contains("HTML 处理: 成功，处理后 "), "{res}");
+        assert!(!res.contains("处理后 1011 字符"), "{res}");
+    }
+}
@@ -0,0 +1,115 @@
+use tracing::info;
+
+use super::ControllerImpl;
+use crate::crawler::Crawler;
+use crate::qbot::{ChannelId, QBotApiClient};
+
+impl<A: QBotApiClient + Sync, C: Crawler + Sync> ControllerImpl<A, C> {
+    pub(super) async fn 重发(&self, channel_id: &ChannelId) -> String {
+        let Some(post) = self.last_sent.lock().unwrap().clone() else {
+            return "还没有发送过日报".into();
+        };
+        if !self.dry_run {
+            if let Err(reply) = self.check_forum_channels(&[channel_id]).await {
+                return reply;
+            }
+        }
+
+        let (title, html, process_error) = self.render_post(&post);
+        if self.dry_run {
+            info!(%channel_id, title = %title, html = %html, "dry run, not resending thread");
+            return format!(
+                "重发成功（演练）: {} - {}{process_error}",
+                post.date,
+                self.sanitizer.sanitize(&post.title)
+            );
+        }
+        match self
+            .api_client
+            .send_channel_thread_html(channel_id, &title, &html)
+            .await
+        {
+            Ok(task_id) => {
+                self.record_sent_threads(post.date, &post, [(channel_id.clone(), task_id)]);
+                format!(
+                    "重发成功: {} - {}{process_error}",
+                    post.date,
+                    self.sanitizer.sanitize(&post.title)
+                )
+            }
+            Err(e) => {
+                self.record_api_error("重发", &e);
+                format!("重发失败: {}", self.sanitizer.sanitize(&e.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crawler::InMemoryCrawler;
+    use crate::post::{DailyPost, DailyPostDate};
+    use crate::qbot::model::Channel;
+    use crate::qbot::InMemoryApiClient;
+
+    use super::*;
+
+    fn forum_channel(id: &str) -> Channel {
+        Channel {
+            id: id.into(),
+            guild_id: "guildId".into(),
+            name: id.into(),
+            channel_type: Channel::TYPE_FORUM,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resend_uses_retained_post() {
+        let api_client = InMemoryApiClient::new();
+        api_client.add_channel(forum_channel("newsChannelId"));
+        api_client.add_channel(forum_channel("newChannelId"));
+        // Nothing to re-crawl from, so the post can only come from the controller.
+        let controller = ControllerImpl::new(
+            api_client,
+            InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        assert_eq!(
+            controller.重发(&"newChannelId".into()).await,
+            "还没有发送过日报"
+        );
+
+        let date: DailyPostDate = "2024-04-11".parse().unwrap();
+        let post = DailyPost {
+            href: "/article?id=1".into(),
+            content_html: "<p>内容</p>".into(),
+            title: "TinyUFO".into(),
+            author: "PsiACE".into(),
+            publish_time: "2024-04-11 16:16".into(),
+            published_at: None,
+            date,
+            tags: vec![],
+            content_hash: 0,
+        };
+        controller.posts.lock().unwrap().insert(date, post);
+        controller
+            .发送(&"guildId".into(), &"channelId".into(), date, &[])
+            .await;
+        assert!(controller.posts.lock().unwrap().is_empty());
+
+        assert_eq!(
+            controller.重发(&"newChannelId".into()).await,
+            "重发成功: 2024-04-11 - TinyUFO"
+        );
+        let threads = controller.api_client.threads();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[1].0, "newChannelId");
+        assert_eq!(threads[1].1, threads[0].1);
+        assert_eq!(threads[1].2, threads[0].2);
+        assert_eq!(
+            controller.sent_threads.lock().unwrap()[&date].threads.len(),
+            2
+        );
+    }
+}
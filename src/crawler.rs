@@ -3,11 +3,15 @@ use std::str::FromStr;
 use std::sync::OnceLock;
 use std::time::Duration;
 
+use encoding_rs::{Encoding, UTF_8};
 use scraper::Selector;
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, warn};
 
-use crate::post::{DailyPost, DailyPostCategory, DailyPostDate, DailyPostTitle};
+use crate::metrics::METRICS;
+use crate::post::{
+    truncate_on_char_boundary, DailyPost, DailyPostCategory, DailyPostDate, DailyPostTitle,
+};
 
 #[derive(Debug, Error)]
 pub enum CrawlerError {
@@ -17,30 +21,270 @@ pub enum CrawlerError {
     HttpStatus(u16),
     #[error("error parsing HTML: {0}")]
     HtmlParseError(String),
+    #[error("no posts found on the category page")]
+    NoPostsFound,
+    #[error("no posts on the category page since {0}")]
+    NoRecentPosts(DailyPostDate),
+    #[error("invalid tag selector {0:?}")]
+    InvalidTagSelector(String),
 }
 
 pub type CrawlerResult<T> = std::result::Result<T, CrawlerError>;
 
+#[cfg(any(test, feature = "testing"))]
+mod in_memory;
+#[cfg(any(test, feature = "testing"))]
+pub use in_memory::InMemoryCrawler;
+
 pub trait Crawler {
     fn fetch_news_category(&self) -> impl Future<Output = CrawlerResult<DailyPostCategory>> + Send;
     fn fetch_post(&self, href: &str) -> impl Future<Output = CrawlerResult<DailyPost>> + Send;
+    /// Posts dated before this are ignored by [`Self::fetch_latest_post`], so that an old article
+    /// resurfacing on the category page isn't picked up.
+    fn min_post_date(&self) -> Option<DailyPostDate> {
+        None
+    }
+    fn fetch_latest_post(&self) -> impl Future<Output = CrawlerResult<DailyPost>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let category = self.fetch_news_category().await?;
+            let min_date = self.min_post_date();
+            let latest = category
+                .posts
+                .into_iter()
+                .filter(|post| min_date.is_none_or(|min_date| post.date >= min_date))
+                .max_by_key(|post| post.date)
+                .ok_or(match min_date {
+                    Some(min_date) => CrawlerError::NoRecentPosts(min_date),
+                    None => CrawlerError::NoPostsFound,
+                })?;
+            self.fetch_post(&latest.href).await
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CrawlerConfig {
+    pub tag_selector: String,
+    pub max_content_bytes: usize,
+    /// Posts older than this many days are ignored when picking the latest one.
+    pub max_post_age_days: Option<u32>,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            tag_selector: ".vice-title .tag".into(),
+            max_content_bytes: 256 * 1024,
+            max_post_age_days: None,
+        }
+    }
+}
+
+const TRUNCATED_NOTE: &str = "<p>（内容过长，已截断，请前往原文链接查看）</p>";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+pub struct CrawlerBuilder {
+    base_url: String,
+    config: CrawlerConfig,
+    timeout: Duration,
+    user_agent: Option<String>,
+    max_retries: u32,
+}
+
+impl CrawlerBuilder {
+    pub fn new() -> Self {
+        Self {
+            base_url: String::new(),
+            config: CrawlerConfig::default(),
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: None,
+            max_retries: 0,
+        }
+    }
+
+    pub fn base_url(self, base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            ..self
+        }
+    }
+
+    pub fn config(self, config: CrawlerConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn tag_selector(mut self, tag_selector: impl Into<String>) -> Self {
+        self.config.tag_selector = tag_selector.into();
+        self
+    }
+
+    pub fn max_content_bytes(mut self, max_content_bytes: usize) -> Self {
+        self.config.max_content_bytes = max_content_bytes;
+        self
+    }
+
+    pub fn max_post_age_days(mut self, max_post_age_days: u32) -> Self {
+        self.config.max_post_age_days = Some(max_post_age_days);
+        self
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        Self {
+            user_agent: Some(user_agent.into()),
+            ..self
+        }
+    }
+
+    /// Number of extra attempts for pages that failed to load or got a 5xx response.
+    pub fn retry(self, max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..self
+        }
+    }
+
+    pub fn build(self) -> CrawlerResult<CrawlerImpl> {
+        let mut builder = reqwest::Client::builder().timeout(self.timeout);
+        if let Some(user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        let tag_selector = Selector::parse(&self.config.tag_selector)
+            .map_err(|_| CrawlerError::InvalidTagSelector(self.config.tag_selector.clone()))?;
+        Ok(CrawlerImpl {
+            base_url: self.base_url,
+            client: builder.build()?,
+            tag_selector,
+            max_content_bytes: self.config.max_content_bytes,
+            max_post_age_days: self.config.max_post_age_days,
+            max_retries: self.max_retries,
+        })
+    }
+}
+
+impl Default for CrawlerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct CrawlerImpl {
     base_url: String,
     client: reqwest::Client,
+    tag_selector: Selector,
+    max_content_bytes: usize,
+    max_post_age_days: Option<u32>,
+    max_retries: u32,
 }
 
 impl CrawlerImpl {
     pub fn new(base_url: String) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
+        Self::with_config(base_url, CrawlerConfig::default())
+            .expect("the default crawler config is valid")
+    }
+
+    pub fn with_config(base_url: String, config: CrawlerConfig) -> CrawlerResult<Self> {
+        CrawlerBuilder::new()
+            .base_url(base_url)
+            .config(config)
             .build()
-            .unwrap();
-        Self { base_url, client }
+    }
+
+    async fn fetch_page_once(&self, path: &str) -> CrawlerResult<String> {
+        let res = self
+            .client
+            .get(format!("{}{path}", self.base_url))
+            .send()
+            .await?;
+        let status = res.status();
+        let content_type = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        let body = res.bytes().await?;
+        let res_text = decode_page(content_type.as_deref(), &body);
+        if status.is_client_error() || status.is_server_error() {
+            let res_text = truncate_on_char_boundary(&res_text, 1024);
+            error!(
+                "unsuccessful response code {}, response: {}",
+                status.as_u16(),
+                res_text
+            );
+            return Err(CrawlerError::HttpStatus(status.as_u16()));
+        }
+        Ok(res_text)
+    }
+
+    async fn fetch_page(&self, path: &str) -> CrawlerResult<String> {
+        METRICS.crawler_fetches.inc();
+        let mut res = self.fetch_page_once(path).await;
+        for attempt in 1..=self.max_retries {
+            let retryable = match &res {
+                Err(CrawlerError::ConnectionError(_)) => true,
+                Err(CrawlerError::HttpStatus(status)) => *status >= 500,
+                _ => false,
+            };
+            if !retryable {
+                break;
+            }
+            warn!(
+                "fetching {} failed ({}), retrying ({}/{})",
+                path,
+                res.as_ref().err().unwrap(),
+                attempt,
+                self.max_retries
+            );
+            tokio::time::sleep(RETRY_DELAY).await;
+            res = self.fetch_page_once(path).await;
+        }
+        res
     }
 }
 
+/// Decodes a page with the charset from `Content-Type`, or else from a `<meta>` tag in the page,
+/// falling back to UTF-8.
+fn decode_page(content_type: Option<&str>, body: &[u8]) -> String {
+    let encoding = content_type
+        .and_then(content_type_charset)
+        .or_else(|| meta_charset(body))
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    encoding.decode(body).0.into_owned()
+}
+
+fn content_type_charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+fn meta_charset(body: &[u8]) -> Option<&str> {
+    // Browsers only look for the declaration near the start of the page, and so does this.
+    let head = &body[..body.len().min(1024)];
+    let start = head
+        .windows(8)
+        .position(|window| window.eq_ignore_ascii_case(b"charset="))?
+        + 8;
+    let value = head[start..].strip_prefix(b"\"").unwrap_or(&head[start..]);
+    let len = value
+        .iter()
+        .position(|b| !(b.is_ascii_alphanumeric() || b"-_:.".contains(b)))
+        .unwrap_or(value.len());
+    std::str::from_utf8(&value[..len]).ok()
+}
+
 fn parse_raw_title(title: &str) -> Option<(DailyPostDate, &str)> {
     let (_prefix, mut remaining) = title.split_once('】')?;
     remaining = remaining.trim_start();
@@ -54,25 +298,9 @@ impl Crawler for CrawlerImpl {
         static ARTICLE_SELECTOR: OnceLock<Selector> = OnceLock::new();
         static TITLE_SELECTOR: OnceLock<Selector> = OnceLock::new();
 
-        let res = self
-            .client
-            .get(&format!(
-                "{}/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f",
-                self.base_url
-            ))
-            .send()
+        let res_text = self
+            .fetch_page("/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
             .await?;
-        let status = res.status();
-        let res_text = res.text().await?;
-        if status.is_client_error() || status.is_server_error() {
-            let res_text = res_text.chars().take(1024).collect::<String>();
-            error!(
-                "unsuccessful response code {}, response: {}",
-                status.as_u16(),
-                res_text
-            );
-            return Err(CrawlerError::HttpStatus(status.as_u16()));
-        }
 
         let html = scraper::Html::parse_document(&res_text);
         let posts = html
@@ -91,60 +319,70 @@ impl Crawler for CrawlerImpl {
                 })
             })
             .collect::<Vec<_>>();
-        if posts.is_empty() && !html.errors.is_empty() {
-            let error = html.errors.join("");
-            error!("error parsing category HTML: {:?}", error);
-            return Err(CrawlerError::HtmlParseError(error));
+        if posts.is_empty() {
+            if !html.errors.is_empty() {
+                let error = html.errors.join("");
+                error!("error parsing category HTML: {:?}", error);
+                return Err(CrawlerError::HtmlParseError(error));
+            }
+            error!("no posts found on the category page, has the markup changed?");
+            return Err(CrawlerError::NoPostsFound);
         }
         Ok(DailyPostCategory { posts })
     }
 
+    fn min_post_date(&self) -> Option<DailyPostDate> {
+        self.max_post_age_days
+            .map(|days| DailyPostDate::today().days_before(days))
+    }
+
     async fn fetch_post(&self, href: &str) -> CrawlerResult<DailyPost> {
         static CONTENT_SELECTOR: OnceLock<Selector> = OnceLock::new();
         static TITLE_SELECTOR: OnceLock<Selector> = OnceLock::new();
         static AUTHOR_SELECTOR: OnceLock<Selector> = OnceLock::new();
         static PUBLISH_TIME_SELECTOR: OnceLock<Selector> = OnceLock::new();
 
-        let res = self
-            .client
-            .get(&format!("{}{href}", self.base_url))
-            .send()
-            .await?;
-        let status = res.status();
-        let res_text = res.text().await?;
-        if status.is_client_error() || status.is_server_error() {
-            let res_text = res_text.chars().take(1024).collect::<String>();
-            error!(
-                "unsuccessful response code {}, response: {}",
-                status.as_u16(),
-                res_text
-            );
-            return Err(CrawlerError::HttpStatus(status.as_u16()));
-        }
+        let res_text = self.fetch_page(href).await?;
 
         let html = scraper::Html::parse_document(&res_text);
-        let content_html = html
+        let mut content_html = String::new();
+        for node in html
             .select(CONTENT_SELECTOR.get_or_init(|| Selector::parse(".detail-body > *").unwrap()))
-            .map(|node| node.html())
-            .collect::<Vec<_>>()
-            .join("");
+        {
+            let node_html = node.html();
+            if content_html.len() + node_html.len() > self.max_content_bytes {
+                warn!(
+                    "post content exceeds {} bytes (href={}), truncating",
+                    self.max_content_bytes, href
+                );
+                content_html.push_str(TRUNCATED_NOTE);
+                break;
+            }
+            content_html.push_str(&node_html);
+        }
         if content_html.is_empty() && !html.errors.is_empty() {
             let error = html.errors.join("");
             error!("error parsing post HTML (href={}): {:?}", href, error);
             return Err(CrawlerError::HtmlParseError(error));
         }
 
-        let title = html
+        let raw_title = html
             .select(
                 TITLE_SELECTOR.get_or_init(|| Selector::parse(".body-content .title a").unwrap()),
             )
             .next()
             .map(|node| node.text().collect::<String>())
             .unwrap_or_default();
-        let (date, title) = parse_raw_title(&title).ok_or_else(|| {
-            error!("error parsing post title (href={}): {:?}", href, title);
-            CrawlerError::HtmlParseError("error parsing post title".to_string())
-        })?;
+        let (date, title) = match parse_raw_title(&raw_title) {
+            Some((date, title)) => (date, title),
+            None => {
+                warn!(
+                    "unexpected post title format (href={}): {:?}, falling back to today",
+                    href, raw_title
+                );
+                (DailyPostDate::today(), raw_title.trim())
+            }
+        };
         let author = html
             .select(AUTHOR_SELECTOR.get_or_init(|| Selector::parse(".vice-title a").unwrap()))
             .next()
@@ -158,15 +396,21 @@ impl Crawler for CrawlerImpl {
             .next()
             .map(|node| node.text().collect::<String>())
             .unwrap_or_default();
+        let tags = html
+            .select(&self.tag_selector)
+            .map(|node| node.text().collect::<String>().trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
 
-        Ok(DailyPost {
-            href: href.into(),
-            content_html,
-            title: title.into(),
+        Ok(DailyPost::new(
+            href.into(),
+            date,
+            title.into(),
             author,
             publish_time,
-            date,
-        })
+            content_html,
+            tags,
+        ))
     }
 }
 
@@ -177,6 +421,12 @@ impl<C: Crawler + Send + Sync> Crawler for std::sync::Arc<C> {
     async fn fetch_post(&self, href: &str) -> CrawlerResult<DailyPost> {
         (**self).fetch_post(href).await
     }
+    fn min_post_date(&self) -> Option<DailyPostDate> {
+        (**self).min_post_date()
+    }
+    async fn fetch_latest_post(&self) -> CrawlerResult<DailyPost> {
+        (**self).fetch_latest_post().await
+    }
 }
 
 #[cfg(test)]
@@ -216,6 +466,21 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_fetch_category_no_posts() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(include_str!(
+                "../tests/fixtures/rustcc_category_no_posts.html"
+            ))
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        let res = crawler.fetch_news_category().await;
+        assert!(matches!(res, Err(CrawlerError::NoPostsFound)));
+    }
+
     #[tokio::test]
     async fn test_fetch_post() {
         let mut mock_server = Server::new_async().await;
@@ -239,6 +504,16 @@ mod tests {
         assert_eq!(post.date, "2024-04-11".parse().unwrap());
         assert_eq!(post.author, "PsiACE");
         assert_eq!(post.publish_time, "2024-04-13 16:16");
+        assert_eq!(
+            post.published_at,
+            Some(
+                chrono::NaiveDate::from_ymd_opt(2024, 4, 13)
+                    .unwrap()
+                    .and_hms_opt(16, 16, 0)
+                    .unwrap()
+            )
+        );
+        assert!(post.tags.is_empty());
         assert!(post.content_html.contains("TinyUFO"));
         assert!(post.content_html.contains("命中率"));
         assert!(post.content_html.contains("Hugging Face"));
@@ -246,4 +521,354 @@ mod tests {
             .content_html
             .contains(r#"<a href="https://github.com/cloudflare/pingora/tree/main/tinyufo""#));
     }
+
+    #[tokio::test]
+    async fn test_fetch_post_gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(include_bytes!(
+                "../tests/fixtures/rustcc_daily_post_article.html"
+            ))
+            .unwrap();
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/article?id=1")
+            .match_header("accept-encoding", mockito::Matcher::Regex("gzip".into()))
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_header("content-encoding", "gzip")
+            .with_body(encoder.finish().unwrap())
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        let post = crawler.fetch_post("/article?id=1").await.unwrap();
+        assert_eq!(post.title, "TinyUFO - 无锁高性能缓存");
+        assert!(post.content_html.contains("命中率"));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_matches_constructor() {
+        let fixture = include_str!("../tests/fixtures/rustcc_daily_post_article.html");
+        let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", href)
+            .with_header("content-type", "text/html")
+            .with_body(fixture)
+            .create_async()
+            .await;
+        let post = CrawlerImpl::new(mock_server.url())
+            .fetch_post(href)
+            .await
+            .unwrap();
+
+        let content_html = scraper::Html::parse_document(fixture)
+            .select(&Selector::parse(".detail-body > *").unwrap())
+            .map(|node| node.html())
+            .collect::<String>();
+        let expected = DailyPost::new(
+            href.into(),
+            "2024-04-11".parse().unwrap(),
+            "TinyUFO - 无锁高性能缓存".into(),
+            "PsiACE".into(),
+            "2024-04-13 16:16".into(),
+            content_html,
+            vec![],
+        );
+        assert_eq!(post.href, expected.href);
+        assert_eq!(post.date, expected.date);
+        assert_eq!(post.title, expected.title);
+        assert_eq!(post.author, expected.author);
+        assert_eq!(post.publish_time, expected.publish_time);
+        assert_eq!(post.published_at, expected.published_at);
+        assert_eq!(post.content_html, expected.content_html);
+        assert_eq!(post.content_hash, expected.content_hash);
+        assert_eq!(post.tags, expected.tags);
+        assert_eq!(post, expected);
+    }
+
+    #[test]
+    fn test_decode_page() {
+        let gbk = encoding_rs::GBK.encode("日报").0;
+        assert_eq!(decode_page(Some("text/html; charset=GBK"), &gbk), "日报");
+        assert_eq!(decode_page(Some("text/html;charset=\"gbk\""), &gbk), "日报");
+        assert_eq!(decode_page(None, "日报".as_bytes()), "日报");
+        assert_eq!(
+            decode_page(Some("text/html; charset=unknown"), "日报".as_bytes()),
+            "日报"
+        );
+        let mut page = b"<meta charset=\"gbk\">".to_vec();
+        page.extend_from_slice(&gbk);
+        assert_eq!(
+            decode_page(Some("text/html"), &page),
+            "<meta charset=\"gbk\">日报"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_gbk() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/article?id=header")
+            .with_header("content-type", "text/html; charset=gbk")
+            .with_body(include_bytes!(
+                "../tests/fixtures/rustcc_daily_post_article_gbk.html"
+            ))
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", "/article?id=meta")
+            .with_header("content-type", "text/html")
+            .with_body(include_bytes!(
+                "../tests/fixtures/rustcc_daily_post_article_gbk.html"
+            ))
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        for href in ["/article?id=header", "/article?id=meta"] {
+            let post = crawler.fetch_post(href).await.unwrap();
+            assert_eq!(post.title, "TinyUFO - 无锁高性能缓存", "{href}");
+            assert!(post.content_html.contains("命中率"), "{href}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_tags() {
+        let mut mock_server = Server::new_async().await;
+        let html = include_str!("../tests/fixtures/rustcc_daily_post_article.html").replacen(
+            "发表于",
+            r#"<span class="tag">Rust</span> <span class="tag"> 日报 </span> 发表于"#,
+            1,
+        );
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html)
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        let post = crawler
+            .fetch_post("/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .await
+            .unwrap();
+        assert_eq!(post.tags, ["Rust", "日报"]);
+        assert_eq!(post.author, "PsiACE");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_content_hash() {
+        let mut mock_server = Server::new_async().await;
+        let html = include_str!("../tests/fixtures/rustcc_daily_post_article.html");
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html)
+            .expect(2)
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html.replacen("命中率", "命中次数", 1))
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+        let first = crawler.fetch_post(href).await.unwrap();
+        let identical = crawler.fetch_post(href).await.unwrap();
+        let changed = crawler.fetch_post(href).await.unwrap();
+        assert_eq!(first.content_hash, identical.content_hash);
+        assert_ne!(first.content_hash, changed.content_hash);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_malformed_title() {
+        let mut mock_server = Server::new_async().await;
+        let html = include_str!("../tests/fixtures/rustcc_daily_post_article.html")
+            .replace("【Rust日报】2024-04-11 TinyUFO", "TinyUFO");
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html)
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::new(mock_server.url());
+        let post = crawler
+            .fetch_post("/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .await
+            .unwrap();
+        assert_eq!(post.title, "TinyUFO - 无锁高性能缓存");
+        assert_eq!(post.date, DailyPostDate::today());
+        assert!(post.content_html.contains("TinyUFO"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_config() {
+        let mut mock_server = Server::new_async().await;
+        let html = include_str!("../tests/fixtures/rustcc_daily_post_article.html").replacen(
+            "发表于",
+            r#"<span class="label">Rust</span> 发表于"#,
+            1,
+        );
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_status(502)
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html)
+            .create_async()
+            .await;
+        let crawler = CrawlerBuilder::new()
+            .base_url(mock_server.url())
+            .tag_selector(".vice-title .label")
+            .timeout(Duration::from_secs(5))
+            .retry(1)
+            .build()
+            .unwrap();
+        let post = crawler
+            .fetch_post("/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .await
+            .unwrap();
+        assert_eq!(post.title, "TinyUFO - 无锁高性能缓存");
+        assert_eq!(post.tags, ["Rust"]);
+    }
+
+    #[test]
+    fn test_builder_invalid_tag_selector() {
+        let res = CrawlerBuilder::new().tag_selector(".label >").build();
+        assert!(
+            matches!(res, Err(CrawlerError::InvalidTagSelector(selector)) if selector == ".label >")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builder_timeout() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(b"")
+            })
+            .create_async()
+            .await;
+        let crawler = CrawlerBuilder::new()
+            .base_url(mock_server.url())
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let err = crawler
+            .fetch_post("/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, CrawlerError::ConnectionError(e) if e.is_timeout()),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_post_truncates_large_content() {
+        let mut mock_server = Server::new_async().await;
+        let paragraphs = "<p>填充内容</p>".repeat(10000);
+        let html = include_str!("../tests/fixtures/rustcc_daily_post_article.html").replacen(
+            r#"<div class="detail-body ">"#,
+            &format!(r#"<div class="detail-body "><p>开头</p>{paragraphs}"#),
+            1,
+        );
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(html)
+            .create_async()
+            .await;
+        let crawler = CrawlerImpl::with_config(
+            mock_server.url(),
+            CrawlerConfig {
+                max_content_bytes: 64 * 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let post = crawler
+            .fetch_post("/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .await
+            .unwrap();
+        assert!(post.content_html.len() <= 64 * 1024 + TRUNCATED_NOTE.len());
+        assert!(post.content_html.starts_with("<p>开头</p><p>填充内容</p>"));
+        assert!(post
+            .content_html
+            .ends_with(&format!("</p>{TRUNCATED_NOTE}")));
+        assert!(!post.content_html.contains("Hugging Face"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_latest_post() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(include_str!("../tests/fixtures/rustcc_category.html"))
+            .create_async()
+            .await;
+        let mock_post = mock_server
+            .mock("GET", "/article?id=8f907ec5-f15c-4651-9e75-58add3aaceb2")
+            .with_body(include_str!(
+                "../tests/fixtures/rustcc_daily_post_article.html"
+            ))
+            .create_async()
+            .await;
+        let crawler = std::sync::Arc::new(CrawlerImpl::new(mock_server.url()));
+        let post = crawler.fetch_latest_post().await.unwrap();
+        assert_eq!(
+            post.href,
+            "/article?id=8f907ec5-f15c-4651-9e75-58add3aaceb2"
+        );
+        mock_post.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_latest_post_ignores_stale_posts() {
+        let today = DailyPostDate::today();
+        let mut mock_server = Server::new_async().await;
+        let mock_category = mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(format!(
+                r#"<div class="article-list"><ul>
+                <li><a href="/article?id=ancient">【Rust日报】 2020-01-01 Ancient</a></li>
+                <li><a href="/article?id=recent">【Rust日报】 {today} Recent</a></li>
+                </ul></div>"#
+            ))
+            .create_async()
+            .await;
+        let mock_recent = mock_server
+            .mock("GET", "/article?id=recent")
+            .with_body(include_str!(
+                "../tests/fixtures/rustcc_daily_post_article.html"
+            ))
+            .create_async()
+            .await;
+        let crawler = CrawlerBuilder::new()
+            .base_url(mock_server.url())
+            .max_post_age_days(7)
+            .build()
+            .unwrap();
+        assert_eq!(crawler.min_post_date(), Some(today.days_before(7)));
+        let post = crawler.fetch_latest_post().await.unwrap();
+        assert_eq!(post.href, "/article?id=recent");
+        mock_recent.assert_async().await;
+
+        mock_category.remove_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(
+                r#"<div class="article-list"><ul>
+                <li><a href="/article?id=ancient">【Rust日报】 2020-01-01 Ancient</a></li>
+                </ul></div>"#,
+            )
+            .create_async()
+            .await;
+        let res = crawler.fetch_latest_post().await;
+        assert!(
+            matches!(res, Err(CrawlerError::NoRecentPosts(date)) if date == today.days_before(7))
+        );
+    }
 }
@@ -0,0 +1,39 @@
+use super::{Crawler, CrawlerError, CrawlerResult};
+use crate::post::{DailyPost, DailyPostCategory, DailyPostTitle};
+
+/// A [`Crawler`] serving a fixed set of posts instead of fetching them from rustcc. Unknown hrefs
+/// fail with a 404 like the real site does.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryCrawler {
+    posts: Vec<DailyPost>,
+}
+
+impl InMemoryCrawler {
+    pub fn new(posts: Vec<DailyPost>) -> Self {
+        Self { posts }
+    }
+}
+
+impl Crawler for InMemoryCrawler {
+    async fn fetch_news_category(&self) -> CrawlerResult<DailyPostCategory> {
+        Ok(DailyPostCategory {
+            posts: self
+                .posts
+                .iter()
+                .map(|post| DailyPostTitle {
+                    title: post.title.clone(),
+                    date: post.date,
+                    href: post.href.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn fetch_post(&self, href: &str) -> CrawlerResult<DailyPost> {
+        self.posts
+            .iter()
+            .find(|post| post.href == href)
+            .cloned()
+            .ok_or(CrawlerError::HttpStatus(404))
+    }
+}
@@ -1,40 +1,315 @@
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 
 use regex::Regex;
-use tracing::{debug, error, info};
+use tokio_util::task::TaskTracker;
+use tracing::{debug, error, info, warn};
 
 use crate::controller::Controller;
-use crate::qbot::ws::{payload::AtMessageCreatePayload, QBotWsMessageHandler};
-use crate::qbot::QBotApiClient;
+use crate::post::{truncate_on_char_boundary, DailyPostDate};
+use crate::qbot::ws::payload::{AtMessageCreatePayload, MessageDeletePayload, ReadyUser};
+use crate::qbot::ws::QBotWsMessageHandler;
+use crate::qbot::{ChannelId, MessageId, QBotApiClient};
+
+mod whitelist;
+
+pub use whitelist::{Whitelist, WhitelistError};
+
+const HELP_TEXT: &str = "爬取 <链接> - 爬取指定链接的文章
+爬取最新 - 爬取最新一期日报
+最新列表 [数量] - 列出网站上最新的文章，默认 5 篇
+清空 - 清空所有待发送的文章
+发送 <日期|今天|昨天> [频道ID...] - 发送指定日期的文章，可指定目标频道
+发布最新 - 爬取最新一期日报并发送到日报频道
+重发 - 将最近发送的日报重新发送到当前频道
+已发送 - 列出已发送的帖子
+更新 <日期|今天|昨天> - 重新爬取已发送的文章，删除原帖后重新发送
+详情 <日期|今天|昨天> - 查看待发送文章的元数据与 HTML 处理结果
+所有频道 [关键词] - 列出频道，可按名称筛选
+广播 <消息> - 向日报频道发送消息
+最近错误 - 查看最近一次命令失败的原因
+诊断 - 查看 WS 连接状态
+添加管理员 <用户ID> - 将用户加入白名单
+移除管理员 <用户ID> - 将用户移出白名单";
 
 struct EventHandlerInner<A, C> {
     api_client: A,
     controller: C,
+    bot_user_id: OnceLock<String>,
+    required_role: Option<String>,
+    whitelist: Arc<Whitelist>,
+    recent_message_ids: Mutex<VecDeque<MessageId>>,
+    // Messages whose commands are still running, and whether they have been deleted since.
+    in_flight_messages: Mutex<HashMap<MessageId, bool>>,
+    split_long_replies: bool,
+    verbose_rejections: bool,
+}
+
+// Replies longer than QQ's message limit are rejected as a whole, so cut them short instead.
+const MAX_REPLY_BYTES: usize = 2048;
+const TRUNCATED_SUFFIX: &str = "…（已截断）";
+
+fn clamp_reply(reply: &str, max_len: usize) -> Cow<'_, str> {
+    if reply.len() <= max_len {
+        return Cow::Borrowed(reply);
+    }
+    let kept = truncate_on_char_boundary(reply, max_len.saturating_sub(TRUNCATED_SUFFIX.len()));
+    Cow::Owned(format!("{kept}{TRUNCATED_SUFFIX}"))
+}
+
+// QQ only accepts a few passive replies per message, so the last chunk is still clamped.
+const MAX_REPLY_CHUNKS: usize = 5;
+
+const DEFAULT_LATEST_LIST_LEN: usize = 5;
+
+fn split_reply(reply: &str, max_len: usize, max_chunks: usize) -> Vec<Cow<'_, str>> {
+    let mut chunks = vec![];
+    let mut rest = reply;
+    while !rest.is_empty() {
+        if rest.len() <= max_len || chunks.len() + 1 >= max_chunks {
+            chunks.push(clamp_reply(rest, max_len));
+            break;
+        }
+        let head = truncate_on_char_boundary(rest, max_len);
+        let cut = match head.rfind('\n') {
+            _ if rest[head.len()..].starts_with('\n') => head.len(),
+            Some(pos) if pos > 0 => pos,
+            _ => head.len(),
+        };
+        chunks.push(Cow::Borrowed(&rest[..cut]));
+        rest = rest[cut..].strip_prefix('\n').unwrap_or(&rest[cut..]);
+    }
+    chunks
+}
+
+enum Rejection {
+    UnknownCommand,
+    BadArgument {
+        command: &'static str,
+        reason: &'static str,
+    },
 }
 
-#[derive(Clone)]
+impl Rejection {
+    fn reply(&self, input: &str, verbose: bool) -> String {
+        match (self, verbose) {
+            (Rejection::UnknownCommand, false) => "不支持的命令".into(),
+            (Rejection::UnknownCommand, true) => {
+                format!("不支持的命令: {input}\n发送“帮助”查看可用命令")
+            }
+            (Rejection::BadArgument { reason, .. }, false) => (*reason).into(),
+            (Rejection::BadArgument { command, reason }, true) => match usage(command) {
+                Some(usage) => format!("{reason}\n用法: {usage}"),
+                None => (*reason).into(),
+            },
+        }
+    }
+}
+
+fn usage(command: &str) -> Option<&'static str> {
+    HELP_TEXT.lines().find_map(|line| {
+        let (usage, _) = line.split_once(" - ")?;
+        (usage.split_whitespace().next() == Some(command)).then_some(usage)
+    })
+}
+
+// Number of message ids remembered to drop redelivered events.
+const RECENT_MESSAGE_CAPACITY: usize = 256;
+
 pub struct EventHandler<A, C> {
     inner: Arc<EventHandlerInner<A, C>>,
+    tasks: TaskTracker,
+}
+
+impl<A, C> Clone for EventHandler<A, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            tasks: self.tasks.clone(),
+        }
+    }
 }
 
 impl<A, C> EventHandler<A, C> {
-    pub fn new(api_client: A, controller: C) -> Self {
+    pub fn new(api_client: A, controller: C, required_role: Option<String>) -> Self {
         Self {
             inner: Arc::new(EventHandlerInner {
                 api_client,
                 controller,
+                bot_user_id: OnceLock::new(),
+                required_role,
+                whitelist: Default::default(),
+                recent_message_ids: Mutex::new(VecDeque::with_capacity(RECENT_MESSAGE_CAPACITY)),
+                in_flight_messages: Default::default(),
+                split_long_replies: false,
+                verbose_rejections: false,
             }),
+            tasks: TaskTracker::new(),
+        }
+    }
+
+    pub fn with_whitelist(mut self, whitelist: Arc<Whitelist>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("handler must be configured before it is cloned")
+            .whitelist = whitelist;
+        self
+    }
+
+    /// Replies to unknown commands and bad arguments from authorized users with a hint on what to
+    /// send instead. Unauthorized users are still ignored.
+    pub fn with_verbose_rejections(mut self, verbose_rejections: bool) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("handler must be configured before it is cloned")
+            .verbose_rejections = verbose_rejections;
+        self
+    }
+
+    /// Sends replies that exceed the length limit as several messages split on line boundaries,
+    /// instead of truncating them.
+    pub fn with_split_long_replies(mut self, split_long_replies: bool) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("handler must be configured before it is cloned")
+            .split_long_replies = split_long_replies;
+        self
+    }
+
+    pub fn controller(&self) -> &C {
+        &self.inner.controller
+    }
+
+    /// Ids of the messages whose commands are still running.
+    pub fn in_flight_message_ids(&self) -> Vec<MessageId> {
+        self.inner
+            .in_flight_messages
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Stops accepting new command tasks and waits for outstanding ones to finish. Returns `false`
+    /// if some tasks are still running after `timeout`.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.tasks.close();
+        let pending = self.tasks.len();
+        if pending > 0 {
+            info!(pending, "waiting for in-flight commands");
         }
+        let drained = tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .is_ok();
+        if !drained {
+            warn!(
+                pending = self.tasks.len(),
+                "in-flight commands did not finish in time"
+            );
+        }
+        drained
     }
 }
 
 impl<A: QBotApiClient, C: Controller> EventHandlerInner<A, C> {
+    fn is_authorized(&self, message: &AtMessageCreatePayload) -> bool {
+        self.whitelist.contains(&message.author.id)
+            || self
+                .required_role
+                .as_ref()
+                .is_some_and(|role| message.member.roles.contains(role))
+    }
+
+    // Role holders may run commands, but only whitelisted users can change the whitelist.
+    fn add_admin(&self, author_id: &str, id: &str) -> String {
+        if !self.whitelist.contains(author_id) {
+            return "只有白名单中的管理员可以修改白名单".into();
+        }
+        match self.whitelist.add(id) {
+            Ok(true) => format!("已添加管理员: {id}"),
+            Ok(false) => format!("{id} 已是管理员"),
+            Err(e) => {
+                error!(error = %e, "failed to save whitelist");
+                format!("保存白名单失败: {e}")
+            }
+        }
+    }
+
+    fn remove_admin(&self, author_id: &str, id: &str) -> String {
+        if !self.whitelist.contains(author_id) {
+            return "只有白名单中的管理员可以修改白名单".into();
+        }
+        match self.whitelist.remove(id) {
+            Ok(true) => format!("已移除管理员: {id}"),
+            Ok(false) => format!("{id} 不是管理员"),
+            Err(WhitelistError::LastAdmin) => "不能移除最后一个管理员".into(),
+            Err(e) => {
+                error!(error = %e, "failed to save whitelist");
+                format!("保存白名单失败: {e}")
+            }
+        }
+    }
+
+    fn reject(&self, input: &str, rejection: Rejection) -> String {
+        let reason = match &rejection {
+            Rejection::UnknownCommand => "unknown command",
+            Rejection::BadArgument { .. } => "bad argument",
+        };
+        info!(input, reason, "command rejected");
+        rejection.reply(input, self.verbose_rejections)
+    }
+
+    fn is_duplicate(&self, message_id: &MessageId) -> bool {
+        let mut recent = self.recent_message_ids.lock().unwrap();
+        if recent.iter().any(|id| id == message_id) {
+            return true;
+        }
+        if recent.len() == RECENT_MESSAGE_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(message_id.clone());
+        false
+    }
+
+    fn is_deleted(&self, message_id: &MessageId) -> bool {
+        self.in_flight_messages
+            .lock()
+            .unwrap()
+            .get(message_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn handle_message_delete(&self, message_id: &MessageId) {
+        if let Some(deleted) = self.in_flight_messages.lock().unwrap().get_mut(message_id) {
+            info!(%message_id, "command message deleted while running");
+            *deleted = true;
+        }
+    }
+
     async fn handle_at_message(&self, message: AtMessageCreatePayload) {
-        const ID_WHITELIST: [&str; 1] = ["1453422017104534300"];
-        if !ID_WHITELIST.contains(&message.author.id.as_str()) {
-            info!(%message.author.id, "not in whitelist, ignore");
+        if self.is_duplicate(&message.id) {
+            info!(%message.id, "duplicate message delivery, ignore");
             return;
         }
+        if self.bot_user_id.get() == Some(&message.author.id) {
+            debug!(%message.author.id, "message sent by the bot itself, ignore");
+            return;
+        }
+        if !self.is_authorized(&message) {
+            info!(%message.author.id, "not in whitelist and missing required role, ignore");
+            return;
+        }
+        self.in_flight_messages
+            .lock()
+            .unwrap()
+            .insert(message.id.clone(), false);
+        self.run_command(&message).await;
+        self.in_flight_messages.lock().unwrap().remove(&message.id);
+    }
+
+    async fn run_command(&self, message: &AtMessageCreatePayload) {
         let filtered = Regex::new(r"<@!\d+>")
             .unwrap()
             .replace_all(&message.content, "")
@@ -42,28 +317,177 @@ impl<A: QBotApiClient, C: Controller> EventHandlerInner<A, C> {
         let mut filtered = filtered.trim();
         filtered = filtered.trim_start_matches('/').trim();
         debug!(filtered = %filtered, "got filtered message");
-        let reply_msg = if let Some(href) = filtered.strip_prefix("爬取") {
-            self.controller.爬取(href.trim()).await
-        } else if let Some(date) = filtered.strip_prefix("发送") {
-            let date = date.trim().parse();
-            if let Ok(date) = date {
-                self.controller.发送(&message.channel_id, date).await
+        let (command, argument, reply_msg) = if filtered == "爬取最新" {
+            ("爬取最新", "", self.controller.爬取最新().await)
+        } else if let Some(n) = filtered.strip_prefix("最新列表") {
+            let n = n.trim();
+            let reply_msg = if n.is_empty() {
+                self.controller.最新列表(DEFAULT_LATEST_LIST_LEN).await
+            } else if let Ok(parsed) = n.parse() {
+                self.controller.最新列表(parsed).await
             } else {
-                "无效的日期格式".into()
-            }
-        } else if filtered == "所有频道" {
-            self.controller.所有频道(&message.guild_id).await
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "最新列表",
+                        reason: "无效的数量",
+                    },
+                )
+            };
+            ("最新列表", n, reply_msg)
+        } else if filtered == "清空" {
+            ("清空", "", self.controller.清空().await)
+        } else if let Some(href) = filtered.strip_prefix("爬取") {
+            let href = href.trim();
+            ("爬取", href, self.controller.爬取(href).await)
+        } else if let Some(args) = filtered.strip_prefix("发送") {
+            let args = args.trim();
+            let mut parts = args.split_whitespace();
+            let date = parts.next().unwrap_or_default();
+            let target_channel_ids = parts.map(ChannelId::from).collect::<Vec<_>>();
+            let today = chrono::Local::now().date_naive();
+            let reply_msg = if let Ok(parsed) = DailyPostDate::parse_relative(date, today) {
+                self.controller
+                    .发送(
+                        &message.guild_id,
+                        &message.channel_id,
+                        parsed,
+                        &target_channel_ids,
+                    )
+                    .await
+            } else {
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "发送",
+                        reason: "无效的日期格式",
+                    },
+                )
+            };
+            ("发送", args, reply_msg)
+        } else if filtered == "发布最新" {
+            let reply_msg = self
+                .controller
+                .发布最新(&message.guild_id, &message.channel_id)
+                .await;
+            ("发布最新", "", reply_msg)
+        } else if filtered == "重发" {
+            ("重发", "", self.controller.重发(&message.channel_id).await)
+        } else if filtered == "已发送" {
+            ("已发送", "", self.controller.已发送().await)
+        } else if let Some(date) = filtered.strip_prefix("更新") {
+            let date = date.trim();
+            let today = chrono::Local::now().date_naive();
+            let reply_msg = if let Ok(parsed) = DailyPostDate::parse_relative(date, today) {
+                self.controller.更新(parsed).await
+            } else {
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "更新",
+                        reason: "无效的日期格式",
+                    },
+                )
+            };
+            ("更新", date, reply_msg)
+        } else if let Some(date) = filtered.strip_prefix("详情") {
+            let date = date.trim();
+            let today = chrono::Local::now().date_naive();
+            let reply_msg = if let Ok(parsed) = DailyPostDate::parse_relative(date, today) {
+                self.controller.详情(parsed).await
+            } else {
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "详情",
+                        reason: "无效的日期格式",
+                    },
+                )
+            };
+            ("详情", date, reply_msg)
+        } else if let Some(keyword) = filtered.strip_prefix("所有频道") {
+            let keyword = keyword.trim();
+            let reply_msg = self.controller.所有频道(&message.guild_id, keyword).await;
+            ("所有频道", keyword, reply_msg)
+        } else if let Some(content) = filtered.strip_prefix("广播") {
+            let content = content.trim();
+            let reply_msg = self.controller.广播(&message.guild_id, content).await;
+            ("广播", content, reply_msg)
+        } else if filtered == "诊断" {
+            ("诊断", "", self.controller.诊断().await)
+        } else if filtered == "最近错误" {
+            ("最近错误", "", self.controller.最近错误().await)
+        } else if let Some(id) = filtered.strip_prefix("添加管理员") {
+            let id = id.trim();
+            let reply_msg = if id.is_empty() {
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "添加管理员",
+                        reason: "请输入用户ID",
+                    },
+                )
+            } else {
+                self.add_admin(&message.author.id, id)
+            };
+            ("添加管理员", id, reply_msg)
+        } else if let Some(id) = filtered.strip_prefix("移除管理员") {
+            let id = id.trim();
+            let reply_msg = if id.is_empty() {
+                self.reject(
+                    filtered,
+                    Rejection::BadArgument {
+                        command: "移除管理员",
+                        reason: "请输入用户ID",
+                    },
+                )
+            } else {
+                self.remove_admin(&message.author.id, id)
+            };
+            ("移除管理员", id, reply_msg)
         } else if filtered == "帮助" {
-            "爬取 <链接> - 爬取指定链接的文章\n发送 <日期> - 发送指定日期的文章".into()
+            ("帮助", "", HELP_TEXT.into())
         } else {
-            "不支持的命令".into()
+            (
+                "",
+                filtered,
+                self.reject(filtered, Rejection::UnknownCommand),
+            )
         };
-        let send_res = self
-            .api_client
-            .reply_text_to_channel_message(&message.id, &message.channel_id, &reply_msg)
-            .await;
-        if let Err(e) = send_res {
-            error!(error = %e, "failed to send message");
+        info!(
+            target: "qbot::audit",
+            author_id = %message.author.id,
+            author_username = %message.author.username,
+            command,
+            argument,
+            timestamp = %message.timestamp,
+            result = %reply_msg.lines().next().unwrap_or_default(),
+            "command executed"
+        );
+        // Commands are not cancelled as they may have side effects, but replying to a deleted
+        // message would only fail.
+        if self.is_deleted(&message.id) {
+            info!(%message.id, "command message deleted, skip reply");
+            return;
+        }
+        let chunks = if self.split_long_replies {
+            split_reply(&reply_msg, MAX_REPLY_BYTES, MAX_REPLY_CHUNKS)
+        } else {
+            vec![clamp_reply(&reply_msg, MAX_REPLY_BYTES)]
+        };
+        for (msg_seq, chunk) in (1..).zip(chunks) {
+            let send_res = self
+                .api_client
+                .reply_text_to_channel_message(&message.id, msg_seq, &message.channel_id, &chunk)
+                .await;
+            if let Err(e) = send_res {
+                if self.is_deleted(&message.id) {
+                    info!(error = %e, "failed to reply to deleted message");
+                } else {
+                    error!(error = %e, "failed to send message");
+                }
+                break;
+            }
         }
     }
 }
@@ -71,6 +495,11 @@ impl<A: QBotApiClient, C: Controller> EventHandlerInner<A, C> {
 impl<A: QBotApiClient + Send + Sync + 'static, C: Controller + Send + Sync + 'static>
     QBotWsMessageHandler for EventHandler<A, C>
 {
+    fn handle_ready(&mut self, bot_user: &ReadyUser) {
+        info!(%bot_user.id, %bot_user.username, "bot identity received");
+        let _ = self.inner.bot_user_id.set(bot_user.id.clone());
+    }
+
     fn handle_at_message(&mut self, message: AtMessageCreatePayload) {
         debug!(
             name: "received at message",
@@ -80,8 +509,509 @@ impl<A: QBotApiClient + Send + Sync + 'static, C: Controller + Send + Sync + 'st
             %message.channel_id,
             %message.guild_id);
         let inner = self.inner.clone();
-        tokio::spawn(async move {
+        self.tasks.spawn(async move {
             inner.handle_at_message(message).await;
         });
     }
+
+    fn handle_message_delete(&mut self, payload: MessageDeletePayload) {
+        self.inner.handle_message_delete(&payload.message.id);
+    }
+
+    fn process_at_message(
+        &mut self,
+        message: AtMessageCreatePayload,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let inner = self.inner.clone();
+        async move { inner.handle_at_message(message).await }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::qbot::{GuildId, InMemoryApiClient, SentMessage};
+    use crate::test_util::capture_logs;
+    use tracing::Level;
+
+    use super::*;
+
+    struct EchoController;
+
+    impl Controller for EchoController {
+        async fn 所有频道(&self, guild_id: &GuildId, keyword: &str) -> String {
+            format!("所有频道 {guild_id} {keyword}")
+        }
+        async fn 爬取(&self, href: &str) -> String {
+            format!("爬取 {href}")
+        }
+        async fn 爬取最新(&self) -> String {
+            "爬取最新".into()
+        }
+        async fn 最新列表(&self, n: usize) -> String {
+            format!("最新列表 {n}")
+        }
+        async fn 发送(
+            &self,
+            guild_id: &GuildId,
+            channel_id: &ChannelId,
+            date: DailyPostDate,
+            target_channel_ids: &[ChannelId],
+        ) -> String {
+            format!("发送 {guild_id} {channel_id} {date} {target_channel_ids:?}")
+        }
+        async fn 发布最新(&self, guild_id: &GuildId, channel_id: &ChannelId) -> String {
+            format!("发布最新 {guild_id} {channel_id}")
+        }
+        async fn 重发(&self, channel_id: &ChannelId) -> String {
+            format!("重发 {channel_id}")
+        }
+        async fn 已发送(&self) -> String {
+            "已发送".into()
+        }
+        async fn 更新(&self, date: DailyPostDate) -> String {
+            format!("更新 {date}")
+        }
+        async fn 详情(&self, date: DailyPostDate) -> String {
+            format!("详情 {date}")
+        }
+        async fn 广播(&self, _guild_id: &GuildId, content: &str) -> String {
+            format!("广播 {content}")
+        }
+        async fn 清空(&self) -> String {
+            "清空".into()
+        }
+        async fn 最近错误(&self) -> String {
+            "最近错误".into()
+        }
+        async fn 诊断(&self) -> String {
+            "诊断".into()
+        }
+    }
+
+    fn event_handler(
+        required_role: Option<&str>,
+    ) -> EventHandler<InMemoryApiClient, EchoController> {
+        EventHandler::new(
+            InMemoryApiClient::new(),
+            EchoController,
+            required_role.map(Into::into),
+        )
+    }
+
+    fn message(author_id: &str, roles: &[&str], content: &str) -> AtMessageCreatePayload {
+        serde_json::from_value(json!({
+            "author": { "avatar": "", "id": author_id, "username": "user" },
+            "channel_id": "channelId",
+            "content": content,
+            "guild_id": "guildId",
+            "id": "messageId",
+            "member": { "joined_at": "2024-01-01T00:00:00+08:00", "roles": roles },
+        }))
+        .unwrap()
+    }
+
+    fn replies(handler: &EventHandler<InMemoryApiClient, EchoController>) -> Vec<String> {
+        handler.inner.api_client.replies()
+    }
+
+    #[tokio::test]
+    async fn test_authorized_by_role() {
+        let handler = event_handler(Some("adminRole"));
+        handler
+            .inner
+            .handle_at_message(message("someone", &["4", "adminRole"], "<@!123> 爬取最新"))
+            .await;
+        assert_eq!(replies(&handler), ["爬取最新"]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_role_ignored() {
+        let handler = event_handler(Some("adminRole"));
+        handler
+            .inner
+            .handle_at_message(message("someone", &["4"], "<@!123> 爬取最新"))
+            .await;
+        assert!(replies(&handler).is_empty());
+
+        let handler = event_handler(None);
+        handler
+            .inner
+            .handle_at_message(message("someone", &["adminRole"], "<@!123> 爬取最新"))
+            .await;
+        assert!(replies(&handler).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_whitelisted_without_role() {
+        let handler = event_handler(Some("adminRole"));
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], "<@!123> 爬取最新"))
+            .await;
+        assert_eq!(replies(&handler), ["爬取最新"]);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let logs = capture_logs();
+
+        let handler = event_handler(Some("adminRole"));
+        handler
+            .inner
+            .handle_at_message(message(
+                "someone",
+                &["adminRole"],
+                "<@!123> 爬取 /article?id=1",
+            ))
+            .await;
+
+        let audit_line = logs.find(Level::INFO, "qbot::audit");
+        assert!(audit_line.contains("author_id=someone"), "{audit_line}");
+        assert!(audit_line.contains("command=\"爬取\""), "{audit_line}");
+        assert!(
+            audit_line.contains("argument=\"/article?id=1\""),
+            "{audit_line}"
+        );
+        assert!(
+            audit_line.contains("result=爬取 /article?id=1"),
+            "{audit_line}"
+        );
+    }
+
+    struct SlowController;
+
+    impl Controller for SlowController {
+        async fn 所有频道(&self, _guild_id: &GuildId, _keyword: &str) -> String {
+            unreachable!("所有频道 called")
+        }
+        async fn 爬取(&self, _href: &str) -> String {
+            unreachable!("爬取 called")
+        }
+        async fn 爬取最新(&self) -> String {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            "爬取最新".into()
+        }
+        async fn 发送(
+            &self,
+            _guild_id: &GuildId,
+            _channel_id: &ChannelId,
+            _date: DailyPostDate,
+            _target_channel_ids: &[ChannelId],
+        ) -> String {
+            unreachable!("发送 called")
+        }
+        async fn 发布最新(&self, _guild_id: &GuildId, _channel_id: &ChannelId) -> String {
+            unreachable!("发布最新 called")
+        }
+        async fn 重发(&self, _channel_id: &ChannelId) -> String {
+            unreachable!("重发 called")
+        }
+        async fn 已发送(&self) -> String {
+            unreachable!("已发送 called")
+        }
+        async fn 更新(&self, _date: DailyPostDate) -> String {
+            unreachable!("更新 called")
+        }
+        async fn 详情(&self, _date: DailyPostDate) -> String {
+            unreachable!("详情 called")
+        }
+        async fn 最新列表(&self, _n: usize) -> String {
+            unreachable!("最新列表 called")
+        }
+        async fn 广播(&self, _guild_id: &GuildId, _content: &str) -> String {
+            unreachable!("广播 called")
+        }
+        async fn 清空(&self) -> String {
+            unreachable!("清空 called")
+        }
+        async fn 最近错误(&self) -> String {
+            unreachable!("最近错误 called")
+        }
+        async fn 诊断(&self) -> String {
+            unreachable!("诊断 called")
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_waits_for_commands() {
+        let mut handler = EventHandler::new(InMemoryApiClient::new(), SlowController, None);
+        QBotWsMessageHandler::handle_at_message(
+            &mut handler,
+            message("1453422017104534300", &[], "<@!123> 爬取最新"),
+        );
+        let started = tokio::time::Instant::now();
+        assert!(handler.shutdown(Duration::from_secs(60)).await);
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+        assert_eq!(handler.inner.api_client.replies(), ["爬取最新"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_timeout() {
+        let logs = capture_logs();
+        let mut handler = EventHandler::new(InMemoryApiClient::new(), SlowController, None);
+        QBotWsMessageHandler::handle_at_message(
+            &mut handler,
+            message("1453422017104534300", &[], "<@!123> 爬取最新"),
+        );
+        assert!(!handler.shutdown(Duration::from_secs(5)).await);
+        assert!(handler.inner.api_client.replies().is_empty());
+        logs.find(Level::WARN, "in-flight commands did not finish in time");
+    }
+
+    #[tokio::test]
+    async fn test_reply_error_logged() {
+        let logs = capture_logs();
+        let api_client = InMemoryApiClient::new();
+        api_client.fail_replies();
+        let handler = EventHandler::new(api_client, EchoController, None);
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], "<@!123> 爬取最新"))
+            .await;
+
+        let error = logs.find(Level::ERROR, "failed to send message");
+        assert!(error.contains("internal error"), "{error}");
+        assert!(handler.inner.in_flight_messages.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_message_ignored() {
+        let handler = event_handler(None);
+        for _ in 0..2 {
+            handler
+                .inner
+                .handle_at_message(message("1453422017104534300", &[], "<@!123> 爬取最新"))
+                .await;
+        }
+        assert_eq!(replies(&handler), ["爬取最新"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_with_target_channels() {
+        let handler = event_handler(None);
+        handler
+            .inner
+            .handle_at_message(message(
+                "1453422017104534300",
+                &[],
+                "<@!123> 发送 2024-04-11 channel1 channel2",
+            ))
+            .await;
+        assert_eq!(
+            replies(&handler),
+            [r#"发送 guildId channelId 2024-04-11 ["channel1", "channel2"]"#]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let handler = event_handler(None);
+        handler
+            .inner
+            .handle_at_message(message(
+                "1453422017104534300",
+                &[],
+                "<@!123> 更新 2024-04-11",
+            ))
+            .await;
+        assert_eq!(replies(&handler), ["更新 2024-04-11"]);
+    }
+
+    #[tokio::test]
+    async fn test_details() {
+        let handler = event_handler(None);
+        handler
+            .inner
+            .handle_at_message(message(
+                "1453422017104534300",
+                &[],
+                "<@!123> 详情 2024-04-11",
+            ))
+            .await;
+        assert_eq!(replies(&handler), ["详情 2024-04-11"]);
+    }
+
+    #[tokio::test]
+    async fn test_latest_list() {
+        let handler = event_handler(None);
+        let contents = [
+            "<@!123> 最新列表",
+            "<@!123> 最新列表 3",
+            "<@!123> 最新列表 三",
+        ];
+        for (i, content) in contents.into_iter().enumerate() {
+            let mut message = message("1453422017104534300", &[], content);
+            message.id = format!("messageId{i}").into();
+            handler.inner.handle_at_message(message).await;
+        }
+        let replies = replies(&handler);
+        assert_eq!(replies[..2], ["最新列表 5", "最新列表 3"]);
+        assert!(replies[2].contains("无效的数量"));
+    }
+
+    #[tokio::test]
+    async fn test_publish_latest() {
+        let handler = event_handler(None);
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], "<@!123> 发布最新"))
+            .await;
+        assert_eq!(replies(&handler), ["发布最新 guildId channelId"]);
+    }
+
+    #[test]
+    fn test_clamp_reply() {
+        assert_eq!(clamp_reply("日报", 6), "日报");
+        assert_eq!(clamp_reply("日报日报日报日报日报", 24), "日报…（已截断）");
+        assert_eq!(clamp_reply("日报日报日报日报日报", 23), "日…（已截断）");
+    }
+
+    #[tokio::test]
+    async fn test_long_reply_clamped() {
+        let handler = event_handler(None);
+        let content = format!("<@!123> 广播 {}", "日报".repeat(1000));
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], &content))
+            .await;
+        let replies = replies(&handler);
+        assert!(replies[0].len() <= MAX_REPLY_BYTES);
+        assert!(replies[0].starts_with("广播 日报日报"));
+        assert!(replies[0].ends_with("…（已截断）"));
+    }
+
+    #[test]
+    fn test_split_reply() {
+        assert_eq!(split_reply("日报", 6, 5), ["日报"]);
+        assert_eq!(
+            split_reply("日报\n日报\n日报", 13, 5),
+            ["日报\n日报", "日报"]
+        );
+        assert_eq!(split_reply("日报日报", 6, 5), ["日报", "日报"]);
+        assert_eq!(
+            split_reply("日报日报日报日报日报\n日报日报日报日报日报", 24, 2),
+            ["日报日报日报日报", "日报…（已截断）"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_long_reply_split() {
+        let handler = event_handler(None).with_split_long_replies(true);
+        let lines = (0..300).map(|i| format!("第{i}行")).collect::<Vec<_>>();
+        let content = format!("<@!123> 广播 {}", lines.join("\n"));
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], &content))
+            .await;
+        let replies = replies(&handler);
+        assert_eq!(replies.len(), 2);
+        let msg_seqs = handler
+            .inner
+            .api_client
+            .sent()
+            .into_iter()
+            .filter_map(|sent| match sent {
+                SentMessage::Reply { msg_seq, .. } => Some(msg_seq),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(msg_seqs, [1, 2]);
+        assert!(replies.iter().all(|reply| reply.len() <= MAX_REPLY_BYTES));
+        assert!(replies[0].starts_with("广播 第0行\n"));
+        assert!(replies[1].ends_with("\n第299行"));
+        assert_eq!(replies.join("\n"), format!("广播 {}", lines.join("\n")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deleted_message_not_replied() {
+        let logs = capture_logs();
+        let mut handler = EventHandler::new(InMemoryApiClient::new(), SlowController, None);
+        QBotWsMessageHandler::handle_at_message(
+            &mut handler,
+            message("1453422017104534300", &[], "<@!123> 爬取最新"),
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        handler.handle_message_delete(
+            serde_json::from_value(json!({
+                "message": { "id": "messageId", "channel_id": "channelId", "guild_id": "guildId" },
+            }))
+            .unwrap(),
+        );
+        assert!(handler.shutdown(Duration::from_secs(60)).await);
+
+        assert!(handler.inner.api_client.replies().is_empty());
+        assert!(handler.inner.in_flight_messages.lock().unwrap().is_empty());
+        logs.find(Level::INFO, "command message deleted, skip reply");
+        assert!(!logs.output().contains("ERROR"), "{}", logs.output());
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_admin() {
+        let handler = event_handler(Some("adminRole"));
+        let commands: [(&str, &[&str], &str); 6] = [
+            ("someone", &["adminRole"], "添加管理员 someone"),
+            ("1453422017104534300", &[], "添加管理员 someone"),
+            ("1453422017104534300", &[], "添加管理员 someone"),
+            ("someone", &[], "移除管理员 1453422017104534300"),
+            ("someone", &[], "移除管理员 someone"),
+            ("someone", &[], "爬取最新"),
+        ];
+        for (i, (author_id, roles, content)) in commands.into_iter().enumerate() {
+            let mut message = message(author_id, roles, &format!("<@!123> {content}"));
+            message.id = format!("message{i}").into();
+            handler.inner.handle_at_message(message).await;
+        }
+        handler
+            .inner
+            .handle_at_message(message("1453422017104534300", &[], "<@!123> 爬取最新"))
+            .await;
+        assert_eq!(
+            replies(&handler),
+            [
+                "只有白名单中的管理员可以修改白名单",
+                "已添加管理员: someone",
+                "someone 已是管理员",
+                "已移除管理员: 1453422017104534300",
+                "不能移除最后一个管理员",
+                "爬取最新",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(usage("发送"), Some("发送 <日期|今天|昨天> [频道ID...]"));
+        assert_eq!(usage("爬取"), Some("爬取 <链接>"));
+        assert_eq!(usage("不存在"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rejections() {
+        let commands = [
+            ("someone", "<@!123> 爬取最新"),
+            ("1453422017104534300", "<@!123> 你好"),
+            ("1453422017104534300", "<@!123> 发送 明天"),
+            ("1453422017104534300", "<@!123> 添加管理员"),
+        ];
+        for verbose in [false, true] {
+            let handler = event_handler(Some("adminRole")).with_verbose_rejections(verbose);
+            for (i, (author_id, content)) in commands.into_iter().enumerate() {
+                let mut message = message(author_id, &[], content);
+                message.id = format!("message{i}").into();
+                handler.inner.handle_at_message(message).await;
+            }
+            let expected = if verbose {
+                [
+                    "不支持的命令: 你好\n发送“帮助”查看可用命令",
+                    "无效的日期格式\n用法: 发送 <日期|今天|昨天> [频道ID...]",
+                    "请输入用户ID\n用法: 添加管理员 <用户ID>",
+                ]
+            } else {
+                ["不支持的命令", "无效的日期格式", "请输入用户ID"]
+            };
+            assert_eq!(replies(&handler), expected);
+        }
+    }
 }
@@ -0,0 +1,141 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use thiserror::Error;
+
+const DEFAULT_ADMIN_IDS: [&str; 1] = ["1453422017104534300"];
+
+#[derive(Debug, Error)]
+pub enum WhitelistError {
+    #[error("cannot remove the last admin")]
+    LastAdmin,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Ids of users allowed to run commands regardless of their roles. Changes are written back to
+/// the file it was loaded from, one id per line.
+pub struct Whitelist {
+    ids: RwLock<BTreeSet<String>>,
+    path: Option<PathBuf>,
+}
+
+impl Default for Whitelist {
+    fn default() -> Self {
+        Self {
+            ids: RwLock::new(DEFAULT_ADMIN_IDS.iter().map(|id| id.to_string()).collect()),
+            path: None,
+        }
+    }
+}
+
+impl Whitelist {
+    /// Loads the whitelist from `path`, falling back to the built-in admins if it doesn't exist
+    /// yet.
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let ids = match std::fs::read_to_string(&path) {
+            Ok(content) => content
+                .lines()
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(Into::into)
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Self::default().ids.into_inner().unwrap()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            ids: RwLock::new(ids),
+            path: Some(path),
+        })
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.read().unwrap().contains(id)
+    }
+
+    /// Returns `false` if `id` was already whitelisted.
+    pub fn add(&self, id: &str) -> io::Result<bool> {
+        let mut ids = self.ids.write().unwrap();
+        if ids.contains(id) {
+            return Ok(false);
+        }
+        let mut updated = ids.clone();
+        updated.insert(id.into());
+        self.save(&updated)?;
+        *ids = updated;
+        Ok(true)
+    }
+
+    /// Returns `false` if `id` wasn't whitelisted. The last admin can't be removed, or nobody
+    /// could change the whitelist anymore.
+    pub fn remove(&self, id: &str) -> Result<bool, WhitelistError> {
+        let mut ids = self.ids.write().unwrap();
+        if !ids.contains(id) {
+            return Ok(false);
+        }
+        if ids.len() == 1 {
+            return Err(WhitelistError::LastAdmin);
+        }
+        let mut updated = ids.clone();
+        updated.remove(id);
+        self.save(&updated)?;
+        *ids = updated;
+        Ok(true)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.read().unwrap().is_empty()
+    }
+
+    fn save(&self, ids: &BTreeSet<String>) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = ids.iter().map(|id| format!("{id}\n")).collect::<String>();
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitelist_persisted() {
+        let path = std::env::temp_dir().join(format!("qbot-whitelist-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let whitelist = Whitelist::load(&path).unwrap();
+        assert!(whitelist.contains("1453422017104534300"));
+        assert!(matches!(
+            whitelist.remove("1453422017104534300"),
+            Err(WhitelistError::LastAdmin)
+        ));
+        assert!(whitelist.add("42").unwrap());
+        assert!(!whitelist.add("42").unwrap());
+        assert!(whitelist.remove("1453422017104534300").unwrap());
+        assert!(!whitelist.remove("1453422017104534300").unwrap());
+
+        let reloaded = Whitelist::load(&path).unwrap();
+        assert!(reloaded.contains("42"));
+        assert!(!reloaded.contains("1453422017104534300"));
+        assert_eq!(reloaded.len(), 1);
+        assert!(matches!(
+            reloaded.remove("42"),
+            Err(WhitelistError::LastAdmin)
+        ));
+        assert!(reloaded.contains("42"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
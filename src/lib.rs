@@ -0,0 +1,8 @@
+pub mod controller;
+pub mod crawler;
+pub mod handler;
+pub mod metrics;
+pub mod post;
+pub mod qbot;
+#[cfg(test)]
+mod test_util;
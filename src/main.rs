@@ -1,14 +1,10 @@
-use std::{future::Future, sync::Arc};
+use std::{collections::HashMap, future::Future, path::PathBuf, sync::Arc, time::Duration};
 
 use tokio::sync::Notify;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-pub mod controller;
-pub mod crawler;
-pub mod handler;
-pub mod post;
-pub mod qbot;
-use qbot::ws::QBotWebSocketAuthGroup;
+use qbot::ws::{Intents, QBotWebSocketAuthGroup, QBotWsConfig, QBotWsDispatchMode};
+use rust_cn_qbot::{controller, crawler, handler, metrics, post, qbot};
 
 #[derive(Debug, thiserror::Error)]
 enum CliError {
@@ -18,10 +14,86 @@ enum CliError {
     QBotWsError(#[from] qbot::QBotWsError),
 }
 
-struct EnvRun<A, H> {
+fn check_envs_enabled(production_enabled: bool, sandbox_enabled: bool) -> Result<(), &'static str> {
+    if production_enabled || sandbox_enabled {
+        Ok(())
+    } else {
+        Err("nothing to run: set QBOT_PRODUCTION_ENABLED or QBOT_SANDBOX_ENABLED to true")
+    }
+}
+
+// Events are dispatched inline unless a queue capacity is given.
+fn dispatch_mode(queue_capacity: Option<&str>) -> Result<QBotWsDispatchMode, &'static str> {
+    match queue_capacity {
+        None => Ok(QBotWsDispatchMode::Inline),
+        Some(capacity) => match capacity.parse() {
+            Ok(capacity) if capacity > 0 => Ok(QBotWsDispatchMode::Queued { capacity }),
+            _ => Err("QBOT_WS_DISPATCH_QUEUE_CAPACITY must be a positive number"),
+        },
+    }
+}
+
+const RUSTCC_BASE_URL: &str = "https://rustcc.cn";
+const USAGE: &str = "usage: rust-cn-qbot [crawl <链接> | send <日期|今天|昨天>]";
+
+// Performed once against the first enabled environment instead of running the event loop.
+#[derive(Debug, PartialEq, Eq)]
+enum OneOffCommand {
+    Crawl(String),
+    Send(String),
+}
+
+fn parse_args(args: &[String]) -> Result<Option<OneOffCommand>, &'static str> {
+    match args {
+        [] => Ok(None),
+        [command, link] if command == "crawl" => Ok(Some(OneOffCommand::Crawl(link.clone()))),
+        [command, date] if command == "send" => Ok(Some(OneOffCommand::Send(date.clone()))),
+        _ => Err(USAGE),
+    }
+}
+
+// The controller only keeps crawled posts in memory, so `send` crawls the post of that date first.
+async fn run_one_off(
+    controller: &impl controller::Controller,
+    crawler: &impl crawler::Crawler,
+    command: OneOffCommand,
+) -> String {
+    match command {
+        OneOffCommand::Crawl(link) => {
+            let href = link.strip_prefix(RUSTCC_BASE_URL).unwrap_or(&link);
+            controller.爬取(href).await
+        }
+        OneOffCommand::Send(date) => {
+            let today = chrono::Local::now().date_naive();
+            let Ok(date) = post::DailyPostDate::parse_relative(&date, today) else {
+                return "无效的日期格式".into();
+            };
+            let category = match crawler.fetch_news_category().await {
+                Ok(category) => category,
+                Err(e) => return format!("爬取失败: {e}"),
+            };
+            let Some(title) = category.posts.iter().find(|post| post.date == date) else {
+                return format!("未找到 {date} 的日报");
+            };
+            let crawl_reply = controller.爬取(&title.href).await;
+            let send_reply = controller
+                .发送(&Default::default(), &Default::default(), date, &[])
+                .await;
+            format!("{crawl_reply}\n{send_reply}")
+        }
+    }
+}
+
+const DEFAULT_HANDLER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct EnvRun<A, Api, C> {
     ws_gateway: String,
     authorizer: Arc<A>,
-    handler: H,
+    handler: handler::EventHandler<Api, C>,
+    handler_shutdown_timeout: Duration,
+    ws_diagnostics: qbot::ws::SharedWsDiagnostics,
+    ws_session_file: Option<PathBuf>,
+    dispatch_mode: QBotWsDispatchMode,
 }
 
 trait RunLoop {
@@ -32,31 +104,81 @@ trait RunLoop {
     ) -> impl Future<Output = qbot::QBotWsResult<()>> + Send;
 }
 
-impl<A: qbot::QBotAuthorizer + Send + Sync, H: qbot::ws::QBotWsMessageHandler + Send> RunLoop
-    for EnvRun<A, H>
+impl<A, Api, C> RunLoop for EnvRun<A, Api, C>
+where
+    A: qbot::QBotAuthorizer + Send + Sync,
+    Api: qbot::QBotApiClient + Send + Sync + 'static,
+    C: controller::Controller + Send + Sync + 'static,
 {
     async fn run_loop(
         self,
         quit_signal: &Notify,
         auth_group: &QBotWebSocketAuthGroup,
     ) -> qbot::QBotWsResult<()> {
-        qbot::ws::run_loop(
+        let res = qbot::ws::run_loop(
             self.ws_gateway,
             &*self.authorizer,
-            self.handler,
+            self.handler.clone(),
             quit_signal,
             auth_group,
+            &QBotWsConfig {
+                intents: Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE,
+                // FIXME: ws get disconnected every minute. Send heartbeat every 30s as a workaround.
+                heartbeat_interval_override: Some(Duration::from_secs(30)),
+                dispatch_mode: self.dispatch_mode,
+                heartbeat_jitter: 0.1,
+                diagnostics: self.ws_diagnostics,
+                session_file: self.ws_session_file,
+            },
         )
-        .await
+        .await;
+        self.handler.shutdown(self.handler_shutdown_timeout).await;
+        res
     }
 }
 
-async fn run_env(
-    crawler: Arc<crawler::CrawlerImpl>,
+struct EnvSettings {
+    api_base_url: String,
+    news_channel_id: qbot::ChannelId,
+    guild_news_channel_ids: HashMap<qbot::GuildId, qbot::ChannelId>,
+    mirror_channel_ids: Vec<qbot::ChannelId>,
+    ws_session_file: Option<PathBuf>,
+}
+
+impl EnvSettings {
+    fn from_env(prefix: &str, api_base_url: &str) -> Self {
+        Self {
+            api_base_url: api_base_url.into(),
+            news_channel_id: std::env::var(format!("QBOT_{prefix}_NEWS_CHANNEL_ID"))
+                .unwrap()
+                .into(),
+            guild_news_channel_ids: guild_channel_ids_from_env(&format!(
+                "QBOT_{prefix}_GUILD_NEWS_CHANNEL_IDS"
+            )),
+            mirror_channel_ids: channel_ids_from_env(&format!("QBOT_{prefix}_MIRROR_CHANNEL_IDS")),
+            ws_session_file: std::env::var_os(format!("QBOT_{prefix}_WS_SESSION_FILE"))
+                .map(Into::into),
+        }
+    }
+
+    fn production() -> Self {
+        Self::from_env("PRODUCTION", "https://api.sgroup.qq.com")
+    }
+
+    fn sandbox() -> Self {
+        Self::from_env("SANDBOX", "https://sandbox.api.sgroup.qq.com")
+    }
+}
+
+type ApiClient = qbot::QBotApiClientImpl<Arc<qbot::QBotCachingAuthorizerImpl>>;
+type EnvController = controller::ControllerImpl<Arc<ApiClient>, Arc<crawler::CrawlerImpl>>;
+type Env = EnvRun<qbot::QBotCachingAuthorizerImpl, Arc<ApiClient>, EnvController>;
+
+async fn create_api_client(
+    api_metrics: Arc<metrics::ApiMetrics>,
     api_base_url: String,
     app_id: &str,
-    news_channel_id: String,
-) -> Result<impl RunLoop, CliError> {
+) -> (Arc<qbot::QBotCachingAuthorizerImpl>, Arc<ApiClient>) {
     let client_secret = std::env::var("QBOT_CLIENT_SECRET").unwrap();
     let authorizer = qbot::QBotCachingAuthorizerImpl::create_and_authorize(
         "https://bots.qq.com".into(),
@@ -66,36 +188,222 @@ async fn run_env(
     .await
     .expect("failed to create authorizer"); // TODO: better error handling
     let authorizer = Arc::new(authorizer);
-    let api_client = Arc::new(qbot::QBotApiClientImpl::new(
-        api_base_url,
-        app_id,
-        authorizer.clone(),
-    ));
+    let api_client = Arc::new(
+        qbot::QBotApiClientBuilder::new()
+            .base_url(api_base_url)
+            .app_id(app_id)
+            .authorizer(authorizer.clone())
+            .build()
+            .expect("QBOT_APP_ID must be a valid header value")
+            .with_metrics(api_metrics),
+    );
+    (authorizer, api_client)
+}
+
+fn create_controller<A>(
+    api_client: A,
+    crawler: Arc<crawler::CrawlerImpl>,
+    settings: EnvSettings,
+) -> controller::ControllerImpl<A, Arc<crawler::CrawlerImpl>> {
+    let dry_run = std::env::var("QBOT_DRY_RUN")
+        .as_deref()
+        .unwrap_or("false")
+        .parse()
+        .expect("QBOT_DRY_RUN must be a boolean");
+    let mut html_config = controller::HtmlProcessConfig::default();
+    if let Ok(max_inline_code_chars) = std::env::var("QBOT_INLINE_CODE_MAX_CHARS") {
+        html_config.max_inline_code_chars = max_inline_code_chars
+            .parse()
+            .expect("QBOT_INLINE_CODE_MAX_CHARS must be a number");
+    }
+    if let Ok(table_mode) = std::env::var("QBOT_TABLE_MODE") {
+        html_config.table_mode = table_mode
+            .parse()
+            .expect("QBOT_TABLE_MODE must be text or placeholder");
+    }
+    let send_summary = std::env::var("QBOT_SEND_SUMMARY_MESSAGE")
+        .as_deref()
+        .unwrap_or("false")
+        .parse()
+        .expect("QBOT_SEND_SUMMARY_MESSAGE must be a boolean");
+    controller::ControllerImpl::new(api_client, crawler, settings.news_channel_id, dry_run)
+        .with_guild_news_channel_ids(settings.guild_news_channel_ids)
+        .with_mirror_channel_ids(settings.mirror_channel_ids)
+        .with_html_config(html_config)
+        .with_site_base_url(RUSTCC_BASE_URL)
+        .with_summary_message(send_summary)
+}
+
+async fn run_env(
+    crawler: Arc<crawler::CrawlerImpl>,
+    api_metrics: Arc<metrics::ApiMetrics>,
+    app_id: &str,
+    settings: EnvSettings,
+    whitelist: Arc<handler::Whitelist>,
+) -> Result<Env, CliError> {
+    let (authorizer, api_client) =
+        create_api_client(api_metrics, settings.api_base_url.clone(), app_id).await;
     let ws_gateway = api_client.get_ws_gateway().await?;
-    let controller = controller::ControllerImpl::new(api_client.clone(), crawler, news_channel_id);
-    let handler = handler::EventHandler::new(api_client, controller);
+    let ws_diagnostics = qbot::ws::SharedWsDiagnostics::default();
+    let ws_session_file = settings.ws_session_file.clone();
+    let controller = create_controller(api_client.clone(), crawler, settings)
+        .with_ws_diagnostics(ws_diagnostics.clone());
+    let required_role = std::env::var("QBOT_ADMIN_ROLE_ID").ok();
+    let split_long_replies = std::env::var("QBOT_SPLIT_LONG_REPLIES")
+        .as_deref()
+        .unwrap_or("false")
+        .parse()
+        .expect("QBOT_SPLIT_LONG_REPLIES must be a boolean");
+    let verbose_rejections = std::env::var("QBOT_VERBOSE_REJECTIONS")
+        .as_deref()
+        .unwrap_or("false")
+        .parse()
+        .expect("QBOT_VERBOSE_REJECTIONS must be a boolean");
+    let handler = handler::EventHandler::new(api_client, controller, required_role)
+        .with_whitelist(whitelist)
+        .with_split_long_replies(split_long_replies)
+        .with_verbose_rejections(verbose_rejections);
+    let handler_shutdown_timeout = std::env::var("QBOT_SHUTDOWN_TIMEOUT_SECS")
+        .map(|secs| {
+            Duration::from_secs(
+                secs.parse()
+                    .expect("QBOT_SHUTDOWN_TIMEOUT_SECS must be a number"),
+            )
+        })
+        .unwrap_or(DEFAULT_HANDLER_SHUTDOWN_TIMEOUT);
+    let dispatch_mode = dispatch_mode(
+        std::env::var("QBOT_WS_DISPATCH_QUEUE_CAPACITY")
+            .ok()
+            .as_deref(),
+    )
+    .unwrap_or_else(|e| panic!("{e}"));
 
     Ok(EnvRun {
         ws_gateway,
         authorizer,
         handler,
+        handler_shutdown_timeout,
+        ws_diagnostics,
+        ws_session_file,
+        dispatch_mode,
     })
 }
 
+// Answers `GET /healthz` with the health of every running environment, so that a probe can tell
+// whether the bot is ready in WS mode.
+async fn serve_health<A, C>(
+    listener: tokio::net::TcpListener,
+    handlers: Vec<handler::EventHandler<A, controller::ControllerImpl<A, C>>>,
+    check_crawler: bool,
+) -> std::io::Result<()>
+where
+    A: qbot::QBotApiClient + Send + Sync + 'static,
+    C: crawler::Crawler + Send + Sync + 'static,
+{
+    use http_body_util::Full;
+    use hyper::body::Bytes;
+    use hyper::{Method, Response, StatusCode};
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handlers = handlers.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(|req| {
+                let handlers = handlers.clone();
+                async move {
+                    if (req.method(), req.uri().path()) != (&Method::GET, "/healthz") {
+                        return Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Full::<Bytes>::default());
+                    }
+                    let mut healthy = true;
+                    let mut body = String::new();
+                    for handler in &handlers {
+                        let status = handler.controller().health(check_crawler).await;
+                        healthy &= status.is_healthy();
+                        body += &status.to_string();
+                    }
+                    let status = if healthy {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    Response::builder()
+                        .status(status)
+                        .body(Full::new(Bytes::from(body)))
+                }
+            });
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                .await
+            {
+                debug!("health connection error: {:?}", err);
+            }
+        });
+    }
+}
+
+// A forced quit still gives in-flight commands this long, so that a post being sent is less likely
+// to be cut off halfway.
+const FORCE_QUIT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Returns `false` if some commands had to be abandoned.
+async fn flush_before_force_quit<A, C>(
+    handlers: &[handler::EventHandler<A, C>],
+    timeout: Duration,
+) -> bool {
+    let drained =
+        futures::future::join_all(handlers.iter().map(|handler| handler.shutdown(timeout))).await;
+    if drained.into_iter().all(|drained| drained) {
+        return true;
+    }
+    let abandoned = handlers
+        .iter()
+        .flat_map(|handler| handler.in_flight_message_ids())
+        .collect::<Vec<_>>();
+    warn!(?abandoned, "force quit, abandoning in-flight commands");
+    false
+}
+
+fn channel_ids_from_env(key: &str) -> Vec<qbot::ChannelId> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+// Parses `guild_id:channel_id` pairs separated by commas.
+fn guild_channel_ids_from_env(key: &str) -> HashMap<qbot::GuildId, qbot::ChannelId> {
+    channel_ids_from_env(key)
+        .into_iter()
+        .map(|pair| {
+            let (guild_id, channel_id) = pair
+                .split_once(':')
+                .unwrap_or_else(|| panic!("{key} must contain guild_id:channel_id pairs"));
+            (guild_id.trim().into(), channel_id.trim().into())
+        })
+        .collect()
+}
+
 async fn run_production(
     enabled: bool,
     crawler: Arc<crawler::CrawlerImpl>,
+    api_metrics: Arc<metrics::ApiMetrics>,
     app_id: &str,
-) -> Result<Option<impl RunLoop>, CliError> {
+    whitelist: Arc<handler::Whitelist>,
+) -> Result<Option<Env>, CliError> {
     if enabled {
         info!("running production");
-        let news_channel_id = std::env::var("QBOT_PRODUCTION_NEWS_CHANNEL_ID").unwrap();
         Ok(Some(
             run_env(
                 crawler,
-                "https://api.sgroup.qq.com".into(),
+                api_metrics,
                 app_id,
-                news_channel_id,
+                EnvSettings::production(),
+                whitelist,
             )
             .await?,
         ))
@@ -108,17 +416,19 @@ async fn run_production(
 async fn run_sandbox(
     enabled: bool,
     crawler: Arc<crawler::CrawlerImpl>,
+    api_metrics: Arc<metrics::ApiMetrics>,
     app_id: &str,
-) -> Result<Option<impl RunLoop>, CliError> {
+    whitelist: Arc<handler::Whitelist>,
+) -> Result<Option<Env>, CliError> {
     if enabled {
         info!("running sandbox");
-        let news_channel_id = std::env::var("QBOT_SANDBOX_NEWS_CHANNEL_ID").unwrap();
         Ok(Some(
             run_env(
                 crawler,
-                "https://sandbox.api.sgroup.qq.com".into(),
+                api_metrics,
                 app_id,
-                news_channel_id,
+                EnvSettings::sandbox(),
+                whitelist,
             )
             .await?,
         ))
@@ -138,26 +448,114 @@ async fn main() {
 
     tracing_subscriber::fmt::init();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let one_off_command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(usage) => {
+            eprintln!("{usage}");
+            std::process::exit(2);
+        }
+    };
+
     let app_id = std::env::var("QBOT_APP_ID").unwrap();
 
+    let api_metrics = Arc::new(metrics::ApiMetrics::default());
+    if let Ok(addr) = std::env::var("QBOT_METRICS_LISTEN_ADDR") {
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .expect("failed to bind metrics listen address");
+        info!("serving metrics on {}", addr);
+        let api_metrics = api_metrics.clone();
+        tokio::spawn(async {
+            if let Err(err) = metrics::serve(listener, api_metrics).await {
+                error!("metrics server error: {:?}", err);
+            }
+        });
+    }
+
     let quit_signal = Notify::const_new();
-    let crawler = Arc::new(crawler::CrawlerImpl::new("https://rustcc.cn".into()));
+    let mut crawler_config = crawler::CrawlerConfig::default();
+    if let Ok(tag_selector) = std::env::var("QBOT_CRAWLER_TAG_SELECTOR") {
+        crawler_config.tag_selector = tag_selector;
+    }
+    if let Ok(max_post_age_days) = std::env::var("QBOT_CRAWLER_MAX_POST_AGE_DAYS") {
+        crawler_config.max_post_age_days = Some(
+            max_post_age_days
+                .parse()
+                .expect("QBOT_CRAWLER_MAX_POST_AGE_DAYS must be a number"),
+        );
+    }
+    let crawler = match crawler::CrawlerImpl::with_config(RUSTCC_BASE_URL.into(), crawler_config) {
+        Ok(crawler) => Arc::new(crawler),
+        Err(e) => {
+            error!("invalid crawler config: {e}");
+            std::process::exit(1);
+        }
+    };
     let production_enabled = std::env::var("QBOT_PRODUCTION_ENABLED")
         .as_deref()
-        .unwrap_or_else(|_| "false")
+        .unwrap_or("false")
         .parse()
         .expect("QBOT_PRODUCTION_ENABLED must be a boolean");
     let sandbox_enabled = std::env::var("QBOT_SANDBOX_ENABLED")
         .as_deref()
-        .unwrap_or_else(|_| "false")
+        .unwrap_or("false")
         .parse()
         .expect("QBOT_SANDBOX_ENABLED must be a boolean");
-    let fut_production = run_production(production_enabled, crawler.clone(), &app_id)
-        .await
-        .expect("Starting production");
-    let fut_sandbox = run_sandbox(sandbox_enabled, crawler, &app_id)
+    if let Err(err) = check_envs_enabled(production_enabled, sandbox_enabled) {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+    if let Some(command) = one_off_command {
+        let settings = if production_enabled {
+            EnvSettings::production()
+        } else {
+            EnvSettings::sandbox()
+        };
+        let (_authorizer, api_client) =
+            create_api_client(api_metrics, settings.api_base_url.clone(), &app_id).await;
+        let controller = create_controller(api_client, crawler.clone(), settings);
+        println!("{}", run_one_off(&controller, &*crawler, command).await);
+        return;
+    }
+    let whitelist = Arc::new(match std::env::var("QBOT_WHITELIST_FILE") {
+        Ok(path) => handler::Whitelist::load(path).expect("failed to load whitelist"),
+        Err(_) => handler::Whitelist::default(),
+    });
+    let fut_production = run_production(
+        production_enabled,
+        crawler.clone(),
+        api_metrics.clone(),
+        &app_id,
+        whitelist.clone(),
+    )
+    .await
+    .expect("Starting production");
+    let fut_sandbox = run_sandbox(sandbox_enabled, crawler, api_metrics, &app_id, whitelist)
         .await
         .expect("Starting sandbox");
+    let handlers = fut_production
+        .iter()
+        .chain(&fut_sandbox)
+        .map(|env| env.handler.clone())
+        .collect::<Vec<_>>();
+    if let Ok(addr) = std::env::var("QBOT_HEALTH_LISTEN_ADDR") {
+        let check_crawler = std::env::var("QBOT_HEALTH_CHECK_CRAWLER")
+            .as_deref()
+            .unwrap_or("false")
+            .parse()
+            .expect("QBOT_HEALTH_CHECK_CRAWLER must be a boolean");
+        let handlers = handlers.clone();
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .expect("failed to bind health listen address");
+        info!("serving health on {}", addr);
+        tokio::spawn(async move {
+            if let Err(err) = serve_health(listener, handlers, check_crawler).await {
+                error!("health server error: {:?}", err);
+            }
+        });
+    }
     let auth_group = QBotWebSocketAuthGroup::new();
     let mut ws_fut = pin!(try_join(
         async {
@@ -181,6 +579,7 @@ async fn main() {
                 info!("received ctrl-c");
                 if std::mem::replace(&mut ctrlc_hit, true) {
                     info!("force quit");
+                    flush_before_force_quit(&handlers, FORCE_QUIT_FLUSH_TIMEOUT).await;
                     return;
                 }
                 quit_signal.notify_waiters();
@@ -198,3 +597,216 @@ async fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mockito::Server;
+
+    use super::*;
+
+    struct StaticAuthorizer;
+
+    impl qbot::QBotAuthorizer for StaticAuthorizer {
+        async fn get_access_token(&self) -> qbot::QBotApiResult<String> {
+            Ok("accessToken".into())
+        }
+    }
+
+    fn one_off_controller(
+        crawler_url: String,
+        dry_run: bool,
+    ) -> controller::ControllerImpl<qbot::QBotApiClientImpl<StaticAuthorizer>, crawler::CrawlerImpl>
+    {
+        controller::ControllerImpl::new(
+            qbot::QBotApiClientImpl::new(String::new(), "appId", StaticAuthorizer),
+            crawler::CrawlerImpl::new(crawler_url),
+            "newsChannelId".into(),
+            dry_run,
+        )
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_args(&args(&[])), Ok(None));
+        assert_eq!(
+            parse_args(&args(&["crawl", "/article?id=1"])),
+            Ok(Some(OneOffCommand::Crawl("/article?id=1".into())))
+        );
+        assert_eq!(
+            parse_args(&args(&["send", "今天"])),
+            Ok(Some(OneOffCommand::Send("今天".into())))
+        );
+        assert_eq!(parse_args(&args(&["send"])), Err(USAGE));
+        assert_eq!(parse_args(&args(&["run", "now"])), Err(USAGE));
+    }
+
+    #[tokio::test]
+    async fn test_one_off_crawl() {
+        let mut mock_server = Server::new_async().await;
+        let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+        let mock = mock_server
+            .mock("GET", href)
+            .with_body(include_str!(
+                "../tests/fixtures/rustcc_daily_post_article.html"
+            ))
+            .create_async()
+            .await;
+        let controller = one_off_controller(mock_server.url(), false);
+        let crawler = crawler::CrawlerImpl::new(mock_server.url());
+
+        let res = run_one_off(
+            &controller,
+            &crawler,
+            OneOffCommand::Crawl(format!("{RUSTCC_BASE_URL}{href}")),
+        )
+        .await;
+        assert_eq!(res, "爬取成功: 2024-04-11 - TinyUFO - 无锁高性能缓存");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_one_off_send() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/section?id=f4703117-7e6b-4caf-aa22-a3ad3db6898f")
+            .with_body(include_str!("../tests/fixtures/rustcc_category.html"))
+            .create_async()
+            .await;
+        mock_server
+            .mock("GET", "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99")
+            .with_body(include_str!(
+                "../tests/fixtures/rustcc_daily_post_article.html"
+            ))
+            .create_async()
+            .await;
+        let controller = one_off_controller(mock_server.url(), true);
+        let crawler = crawler::CrawlerImpl::new(mock_server.url());
+
+        let res = run_one_off(
+            &controller,
+            &crawler,
+            OneOffCommand::Send("2024-04-11".into()),
+        )
+        .await;
+        assert_eq!(
+            res,
+            "爬取成功: 2024-04-11 - TinyUFO - 无锁高性能缓存\n发送成功（演练）: 2024-04-11 - TinyUFO - 无锁高性能缓存"
+        );
+        let res = run_one_off(
+            &controller,
+            &crawler,
+            OneOffCommand::Send("2000-01-01".into()),
+        )
+        .await;
+        assert_eq!(res, "未找到 2000-01-01 的日报");
+    }
+
+    #[test]
+    fn test_check_envs_enabled() {
+        assert!(check_envs_enabled(false, false).is_err());
+        assert!(check_envs_enabled(true, false).is_ok());
+        assert!(check_envs_enabled(false, true).is_ok());
+    }
+
+    #[test]
+    fn test_dispatch_mode() {
+        assert_eq!(dispatch_mode(None), Ok(QBotWsDispatchMode::Inline));
+        assert_eq!(
+            dispatch_mode(Some("16")),
+            Ok(QBotWsDispatchMode::Queued { capacity: 16 })
+        );
+        assert!(dispatch_mode(Some("0")).is_err());
+        assert!(dispatch_mode(Some("many")).is_err());
+    }
+
+    async fn spawn_health_server<A>(api_client: A) -> String
+    where
+        A: qbot::QBotApiClient + Send + Sync + 'static,
+    {
+        let api_client = Arc::new(api_client);
+        let controller = controller::ControllerImpl::new(
+            api_client.clone(),
+            crawler::InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        let handler = handler::EventHandler::new(api_client, controller, None);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_health(listener, vec![handler], true));
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn test_serve_health() {
+        let url = spawn_health_server(qbot::InMemoryApiClient::new()).await;
+        let res = reqwest::get(format!("{url}/healthz")).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.text().await.unwrap(), "qq_api: ok\ncrawler: ok\n");
+        let res = reqwest::get(format!("{url}/")).await.unwrap();
+        assert_eq!(res.status(), 404);
+
+        // Nothing listens on port 1.
+        let url = spawn_health_server(qbot::QBotApiClientImpl::new(
+            "http://127.0.0.1:1".into(),
+            "appId",
+            StaticAuthorizer,
+        ))
+        .await;
+        let res = reqwest::get(format!("{url}/healthz")).await.unwrap();
+        assert_eq!(res.status(), 503);
+        assert!(res.text().await.unwrap().starts_with("qq_api: error: "));
+    }
+
+    #[tokio::test]
+    async fn test_force_quit_flush_is_bounded() {
+        let mut mock_server = Server::new_async().await;
+        mock_server
+            .mock("GET", "/article?id=1")
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_secs(2));
+                w.write_all(b"")
+            })
+            .create_async()
+            .await;
+        let api_client = Arc::new(qbot::InMemoryApiClient::new());
+        let controller = controller::ControllerImpl::new(
+            api_client.clone(),
+            crawler::CrawlerImpl::new(mock_server.url()),
+            "newsChannelId".into(),
+            false,
+        );
+        let mut handler = handler::EventHandler::new(api_client, controller, None);
+        let message = serde_json::from_value(serde_json::json!({
+            "author": { "avatar": "", "id": "1453422017104534300", "username": "admin" },
+            "channel_id": "channelId",
+            "content": "<@!123> 爬取 /article?id=1",
+            "guild_id": "guildId",
+            "id": "messageId",
+            "member": { "joined_at": "2024-01-01T00:00:00+08:00", "roles": [] },
+        }))
+        .unwrap();
+        qbot::ws::QBotWsMessageHandler::handle_at_message(&mut handler, message);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let started = std::time::Instant::now();
+        let drained = flush_before_force_quit(&[handler.clone()], Duration::from_millis(200)).await;
+        assert!(!drained);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(handler.in_flight_message_ids(), ["messageId"]);
+    }
+
+    #[tokio::test]
+    async fn test_force_quit_flush_without_commands() {
+        let api_client = Arc::new(qbot::InMemoryApiClient::new());
+        let controller = controller::ControllerImpl::new(
+            api_client.clone(),
+            crawler::InMemoryCrawler::default(),
+            "newsChannelId".into(),
+            false,
+        );
+        let handler = handler::EventHandler::new(api_client, controller, None);
+        assert!(flush_before_force_quit(&[handler], FORCE_QUIT_FLUSH_TIMEOUT).await);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tracing::debug;
+
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub struct Histogram {
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; DURATION_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(i) = DURATION_BUCKETS.iter().position(|&le| secs <= le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (le, bucket) in DURATION_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiCallOutcome {
+    Success,
+    ApiError,
+    RequestError,
+    AccessTokenError,
+}
+
+impl ApiCallOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApiCallOutcome::Success => "success",
+            ApiCallOutcome::ApiError => "api_error",
+            ApiCallOutcome::RequestError => "request_error",
+            ApiCallOutcome::AccessTokenError => "access_token_error",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApiMetrics {
+    calls: Mutex<BTreeMap<(&'static str, ApiCallOutcome), u64>>,
+}
+
+impl ApiMetrics {
+    pub fn record(&self, endpoint: &'static str, outcome: ApiCallOutcome) {
+        *self
+            .calls
+            .lock()
+            .unwrap()
+            .entry((endpoint, outcome))
+            .or_default() += 1;
+    }
+
+    pub fn get(&self, endpoint: &str, outcome: ApiCallOutcome) -> u64 {
+        self.calls
+            .lock()
+            .unwrap()
+            .get(&(endpoint, outcome))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn render(&self, out: &mut String) {
+        let name = "qbot_api_calls_total";
+        let _ = writeln!(
+            out,
+            "# HELP {name} QQ bot API calls by endpoint and outcome."
+        );
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for ((endpoint, outcome), count) in self.calls.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{name}{{endpoint=\"{endpoint}\",outcome=\"{}\"}} {count}",
+                outcome.as_str()
+            );
+        }
+    }
+}
+
+pub struct Metrics {
+    pub ws_events_received: Counter,
+    pub ws_reconnects: Counter,
+    pub ws_malformed_events: Counter,
+    pub api_call_duration: Histogram,
+    pub crawler_fetches: Counter,
+    pub token_refreshes: Counter,
+}
+
+pub static METRICS: Metrics = Metrics {
+    ws_events_received: Counter::new(),
+    ws_reconnects: Counter::new(),
+    ws_malformed_events: Counter::new(),
+    api_call_duration: Histogram::new(),
+    crawler_fetches: Counter::new(),
+    token_refreshes: Counter::new(),
+};
+
+fn render_counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    let _ = writeln!(out, "{name} {}", counter.get());
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        render_counter(
+            &mut out,
+            "qbot_ws_events_received_total",
+            "Dispatch events received from the WebSocket gateway.",
+            &self.ws_events_received,
+        );
+        render_counter(
+            &mut out,
+            "qbot_ws_reconnects_total",
+            "WebSocket reconnect attempts.",
+            &self.ws_reconnects,
+        );
+        render_counter(
+            &mut out,
+            "qbot_ws_malformed_events_total",
+            "Known dispatch events whose payload failed to parse.",
+            &self.ws_malformed_events,
+        );
+        self.api_call_duration.render(
+            &mut out,
+            "qbot_api_call_duration_seconds",
+            "Latency of QQ bot API calls.",
+        );
+        render_counter(
+            &mut out,
+            "qbot_crawler_fetches_total",
+            "Pages fetched by the crawler.",
+            &self.crawler_fetches,
+        );
+        render_counter(
+            &mut out,
+            "qbot_token_refreshes_total",
+            "Access token refreshes.",
+            &self.token_refreshes,
+        );
+        out
+    }
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    api_metrics: &ApiMetrics,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            let mut body = METRICS.render();
+            api_metrics.render(&mut body);
+            Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Full::new(Bytes::from(body)))
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::default()),
+    };
+    Ok(res.unwrap())
+}
+
+pub async fn serve(listener: TcpListener, api_metrics: Arc<ApiMetrics>) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let api_metrics = api_metrics.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|req| {
+                let api_metrics = api_metrics.clone();
+                async move { handle(req, &api_metrics).await }
+            });
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("metrics connection error: {:?}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scrape_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let api_metrics = Arc::new(ApiMetrics::default());
+        api_metrics.record("list_channels", ApiCallOutcome::Success);
+        tokio::spawn(serve(listener, api_metrics));
+        METRICS.ws_reconnects.inc();
+
+        let res = reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+        let body = res.text().await.unwrap();
+        assert!(body.contains("# TYPE qbot_ws_events_received_total counter"));
+        assert!(body.contains("qbot_api_call_duration_seconds_bucket{le=\"+Inf\"}"));
+        assert!(!body.contains("qbot_ws_reconnects_total 0\n"));
+        assert!(
+            body.contains("qbot_api_calls_total{endpoint=\"list_channels\",outcome=\"success\"} 1")
+        );
+
+        let res = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(res.status(), 404);
+    }
+}
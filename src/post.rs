@@ -7,6 +7,42 @@ pub struct DailyPostDate {
     pub day: u8,
 }
 
+impl DailyPostDate {
+    fn from_naive(date: chrono::NaiveDate) -> Self {
+        use chrono::Datelike;
+
+        Self {
+            year: date.year() as u16,
+            month: date.month() as u8,
+            day: date.day() as u8,
+        }
+    }
+
+    pub fn today() -> Self {
+        Self::from_naive(chrono::Local::now().date_naive())
+    }
+
+    pub fn yesterday() -> Self {
+        Self::from_naive(chrono::Local::now().date_naive().pred_opt().unwrap())
+    }
+
+    pub fn days_before(self, days: u32) -> Self {
+        let date =
+            chrono::NaiveDate::from_ymd_opt(self.year.into(), self.month.into(), self.day.into())
+                .expect("invalid date");
+        Self::from_naive(date - chrono::Days::new(days.into()))
+    }
+
+    /// Like `FromStr`, but also accepts 今天 and 昨天 relative to `today`.
+    pub fn parse_relative(s: &str, today: chrono::NaiveDate) -> Result<Self, &'static str> {
+        match s {
+            "今天" => Ok(Self::from_naive(today)),
+            "昨天" => today.pred_opt().map(Self::from_naive).ok_or("Invalid date"),
+            _ => s.parse(),
+        }
+    }
+}
+
 impl Display for DailyPostDate {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
@@ -58,5 +94,182 @@ pub struct DailyPost {
     pub title: String,
     pub author: String,
     pub publish_time: String,
+    pub published_at: Option<chrono::NaiveDateTime>,
     pub date: DailyPostDate,
+    pub tags: Vec<String>,
+    pub content_hash: u64,
+}
+
+impl DailyPost {
+    /// Parses `publish_time` and hashes `content_html` to fill in the derived fields.
+    pub fn new(
+        href: String,
+        date: DailyPostDate,
+        title: String,
+        author: String,
+        publish_time: String,
+        content_html: String,
+        tags: Vec<String>,
+    ) -> Self {
+        Self {
+            href,
+            content_hash: hash_content(&content_html),
+            content_html,
+            title,
+            author,
+            published_at: parse_publish_time(&publish_time),
+            publish_time,
+            date,
+            tags,
+        }
+    }
+}
+
+fn parse_publish_time(publish_time: &str) -> Option<chrono::NaiveDateTime> {
+    const FORMATS: [&str; 4] = [
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+    ];
+    let publish_time = publish_time.trim();
+    FORMATS
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(publish_time, format).ok())
+}
+
+// FNV-1a, so the hash stays the same across builds and Rust versions.
+pub fn hash_content(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Cuts `text` to at most `max_bytes` bytes without splitting a multibyte character.
+pub fn truncate_on_char_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+const WORDS_PER_MINUTE: usize = 250;
+
+/// Approximate length of a post. Each CJK character counts as a word, the way Chinese text is
+/// usually counted, and so does each run of other letters and digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadingStats {
+    pub words: usize,
+    pub minutes: usize,
+}
+
+impl ReadingStats {
+    pub fn from_text(text: &str) -> Self {
+        let mut words = 0;
+        let mut in_word = false;
+        for c in text.chars() {
+            if is_cjk(c) {
+                words += 1;
+                in_word = false;
+            } else if c.is_alphanumeric() {
+                words += usize::from(!in_word);
+                in_word = true;
+            } else {
+                in_word = false;
+            }
+        }
+        Self {
+            words,
+            minutes: words.div_ceil(WORDS_PER_MINUTE).max(1),
+        }
+    }
+
+    pub fn from_html(html: &str) -> Self {
+        let fragment = scraper::Html::parse_fragment(html);
+        // Separate text nodes so that words in adjacent elements aren't merged.
+        Self::from_text(&fragment.root_element().text().collect::<Vec<_>>().join(" "))
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{3040}'..='\u{30ff}'
+            | '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{ac00}'..='\u{d7af}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{20000}'..='\u{2ebef}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_publish_time() {
+        let expected = chrono::NaiveDate::from_ymd_opt(2024, 4, 13)
+            .unwrap()
+            .and_hms_opt(16, 16, 30)
+            .unwrap();
+        assert_eq!(parse_publish_time("2024-04-13 16:16:30"), Some(expected));
+        assert_eq!(parse_publish_time("2024-04-13T16:16:30"), Some(expected));
+        assert_eq!(parse_publish_time("3 天前"), None);
+        assert_eq!(parse_publish_time(""), None);
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let parse = |s| DailyPostDate::parse_relative(s, today).map(|d| d.to_string());
+        assert_eq!(parse("今天"), Ok("2024-03-01".into()));
+        assert_eq!(parse("昨天"), Ok("2024-02-29".into()));
+        assert_eq!(parse("2024-04-11"), Ok("2024-04-11".into()));
+        assert!(parse("前天").is_err());
+    }
+
+    #[test]
+    fn test_days_before() {
+        let date: DailyPostDate = "2024-03-01".parse().unwrap();
+        assert_eq!(date.days_before(0), date);
+        assert_eq!(date.days_before(1).to_string(), "2024-02-29");
+        assert_eq!(date.days_before(366).to_string(), "2023-03-01");
+    }
+
+    #[test]
+    fn test_truncate_on_char_boundary() {
+        let text = "日报ab";
+        assert_eq!(truncate_on_char_boundary(text, 100), text);
+        assert_eq!(truncate_on_char_boundary(text, 6), "日报");
+        assert_eq!(truncate_on_char_boundary(text, 5), "日");
+        assert_eq!(truncate_on_char_boundary(text, 4), "日");
+        assert_eq!(truncate_on_char_boundary(text, 2), "");
+        assert_eq!(truncate_on_char_boundary(text, 7), "日报a");
+    }
+
+    #[test]
+    fn test_reading_stats() {
+        let stats = ReadingStats::from_text;
+        assert_eq!(stats("").words, 0);
+        assert_eq!(stats("").minutes, 1);
+        assert_eq!(stats("Rust 日报").words, 3);
+        assert_eq!(stats("用 tokio-util 1.0 写的 TaskTracker。").words, 8);
+        assert_eq!(stats("こんにちは world").words, 6);
+        assert_eq!(stats(&"字".repeat(1200)).minutes, 5);
+        assert_eq!(stats(&"word ".repeat(250)).minutes, 1);
+        assert_eq!(stats(&"word ".repeat(251)).minutes, 2);
+    }
+
+    #[test]
+    fn test_reading_stats_from_html() {
+        let stats = ReadingStats::from_html(
+            "<h1>Rust</h1><p>日报 &amp; <a href=\"/x\">link</a></p><pre>fn main</pre>",
+        );
+        assert_eq!(stats.words, 6);
+    }
 }
@@ -1,9 +1,15 @@
 mod api;
 mod authorizer;
 mod error;
+mod id;
 mod json_u64;
 pub mod ws;
 
-pub use api::{QBotApiClient, QBotApiClientImpl};
+pub use api::{model, QBotApiClient, QBotApiClientBuilder, QBotApiClientImpl};
+#[cfg(any(test, feature = "testing"))]
+pub use api::{InMemoryApiClient, SentMessage};
+#[cfg(test)]
+pub use authorizer::MockAuthorizer;
 pub use authorizer::{QBotAuthorizer, QBotCachingAuthorizerImpl};
 pub use error::{QBotApiError, QBotApiResult, QBotWsError, QBotWsResult};
+pub use id::{ChannelId, GuildId, MessageId};
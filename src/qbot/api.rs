@@ -1,29 +1,84 @@
 use std::future::Future;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use serde::{Deserialize, Serialize};
-use tracing::debug;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{debug, warn};
 
+#[cfg(any(test, feature = "testing"))]
+mod in_memory;
 pub mod model;
 
-use super::{error::QBotApiResultFromResponseExt, QBotApiResult, QBotAuthorizer};
+#[cfg(any(test, feature = "testing"))]
+pub use in_memory::{InMemoryApiClient, SentMessage};
+
+use super::{
+    error::QBotApiResultFromResponseExt, ChannelId, GuildId, MessageId, QBotApiError,
+    QBotApiResult, QBotAuthorizer,
+};
+use crate::metrics::{ApiCallOutcome, ApiMetrics, METRICS};
 
 pub trait QBotApiClient {
+    /// Returns the bot's own user.
+    fn get_me(&self) -> impl Future<Output = QBotApiResult<model::User>> + Send;
     fn list_channels(
         &self,
-        guild_id: &str,
+        guild_id: &GuildId,
     ) -> impl Future<Output = QBotApiResult<Vec<model::Channel>>> + Send;
+    fn get_channel(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<model::Channel>> + Send;
+    fn list_guild_roles(
+        &self,
+        guild_id: &GuildId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Role>>> + Send;
+    /// `msg_seq` lets QQ drop a reply it has already accepted for `message_id`, so a retried
+    /// request is not posted twice. Replies to the same message must use increasing values.
     fn reply_text_to_channel_message(
         &self,
-        message_id: &str,
-        channel_id: &str,
+        message_id: &MessageId,
+        msg_seq: u32,
+        channel_id: &ChannelId,
         content: &str,
     ) -> impl Future<Output = QBotApiResult<()>> + Send;
+    /// Returns the id of the task creating the thread. QQ creates threads asynchronously, so this
+    /// is not the thread id [`QBotApiClient::delete_thread`] takes; find the thread with
+    /// [`QBotApiClient::list_threads`] instead.
     fn send_channel_thread_html(
         &self,
-        channel_id: &str,
+        channel_id: &ChannelId,
         title: &str,
         html: &str,
+    ) -> impl Future<Output = QBotApiResult<String>> + Send;
+    /// Lists the latest threads in a forum channel.
+    fn list_threads(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Thread>>> + Send;
+    fn delete_thread(
+        &self,
+        channel_id: &ChannelId,
+        thread_id: &str,
+    ) -> impl Future<Output = QBotApiResult<()>> + Send;
+    /// Sends a proactive message, i.e. one that doesn't reply to a `msg_id`. QQ only allows a
+    /// handful of these per channel per day, so prefer replies where possible.
+    fn send_text_to_channel(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> impl Future<Output = QBotApiResult<()>> + Send;
+    /// Mutes the member until `mute_end_timestamp`, in seconds since the Unix epoch.
+    fn mute_member(
+        &self,
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> impl Future<Output = QBotApiResult<()>> + Send;
+    fn unmute_member(
+        &self,
+        guild_id: &GuildId,
+        user_id: &str,
     ) -> impl Future<Output = QBotApiResult<()>> + Send;
 }
 
@@ -31,77 +86,399 @@ pub struct QBotApiClientImpl<A> {
     base_url: String,
     client: reqwest::Client,
     authorizer: A,
+    metrics: Arc<ApiMetrics>,
+    max_retries: u32,
+    thread_timeout: Duration,
+    thread_max_retries: u32,
 }
 
-impl<A> QBotApiClientImpl<A> {
-    pub fn new(base_url: String, app_id: &str, authorizer: A) -> Self {
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+// QQ renders the HTML of new threads server-side, which can take a while.
+const DEFAULT_THREAD_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_THREAD_RETRIES: u32 = 2;
+// Doubled after every retry, up to MAX_RETRY_DELAY.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    max_retries: u32,
+    // A request that failed to send or timed out may still have reached QQ, so retrying it is
+    // only safe when sending it twice is harmless.
+    retry_request_errors: bool,
+}
+
+pub struct QBotApiClientBuilder<A> {
+    base_url: String,
+    app_id: String,
+    authorizer: A,
+    timeout: Duration,
+    thread_timeout: Duration,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    max_retries: u32,
+    thread_max_retries: u32,
+}
+
+impl QBotApiClientBuilder<()> {
+    pub fn new() -> Self {
+        Self {
+            base_url: String::new(),
+            app_id: String::new(),
+            authorizer: (),
+            timeout: DEFAULT_TIMEOUT,
+            thread_timeout: DEFAULT_THREAD_TIMEOUT,
+            user_agent: None,
+            proxy: None,
+            max_retries: 0,
+            thread_max_retries: DEFAULT_THREAD_RETRIES,
+        }
+    }
+}
+
+impl Default for QBotApiClientBuilder<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> QBotApiClientBuilder<A> {
+    pub fn base_url(self, base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            ..self
+        }
+    }
+
+    pub fn app_id(self, app_id: impl Into<String>) -> Self {
+        Self {
+            app_id: app_id.into(),
+            ..self
+        }
+    }
+
+    pub fn authorizer<B>(self, authorizer: B) -> QBotApiClientBuilder<B> {
+        QBotApiClientBuilder {
+            base_url: self.base_url,
+            app_id: self.app_id,
+            authorizer,
+            timeout: self.timeout,
+            thread_timeout: self.thread_timeout,
+            user_agent: self.user_agent,
+            proxy: self.proxy,
+            max_retries: self.max_retries,
+            thread_max_retries: self.thread_max_retries,
+        }
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Timeout for creating threads, used instead of [`Self::timeout`].
+    pub fn thread_timeout(self, thread_timeout: Duration) -> Self {
+        Self {
+            thread_timeout,
+            ..self
+        }
+    }
+
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        Self {
+            user_agent: Some(user_agent.into()),
+            ..self
+        }
+    }
+
+    /// Routes all API requests through the given proxy URL.
+    pub fn proxy(self, proxy: impl Into<String>) -> Self {
+        Self {
+            proxy: Some(proxy.into()),
+            ..self
+        }
+    }
+
+    /// Number of extra attempts for requests that failed to send or got a 429 or 5xx response.
+    /// Messages that failed to send are not retried, as QQ may have sent them anyway.
+    pub fn retry(self, max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..self
+        }
+    }
+
+    /// Number of extra attempts for creating a thread that got a 429 or 5xx response, used
+    /// instead of [`Self::retry`]. Threads that failed to send are never retried, as QQ may have
+    /// created them anyway.
+    pub fn thread_retry(self, thread_max_retries: u32) -> Self {
+        Self {
+            thread_max_retries,
+            ..self
+        }
+    }
+
+    pub fn build(self) -> QBotApiResult<QBotApiClientImpl<A>> {
         use reqwest::header;
         let mut headers = header::HeaderMap::new();
         headers.append(
             "X-Union-Appid",
-            header::HeaderValue::from_str(app_id).unwrap(),
+            header::HeaderValue::from_str(&self.app_id)
+                .map_err(|_| QBotApiError::InvalidAppId(self.app_id.clone()))?,
         );
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .default_headers(headers)
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .default_headers(headers);
+        if let Some(user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(QBotApiClientImpl {
+            base_url: self.base_url,
+            client: builder.build()?,
+            authorizer: self.authorizer,
+            metrics: Default::default(),
+            max_retries: self.max_retries,
+            thread_timeout: self.thread_timeout,
+            thread_max_retries: self.thread_max_retries,
+        })
+    }
+}
+
+impl<A> QBotApiClientImpl<A> {
+    pub fn new(base_url: String, app_id: &str, authorizer: A) -> Self {
+        QBotApiClientBuilder::new()
+            .base_url(base_url)
+            .app_id(app_id)
+            .authorizer(authorizer)
             .build()
-            .unwrap();
-        Self {
-            base_url,
-            client,
-            authorizer,
+            .unwrap()
+    }
+
+    pub fn with_metrics(self, metrics: Arc<ApiMetrics>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    pub fn metrics(&self) -> &Arc<ApiMetrics> {
+        &self.metrics
+    }
+}
+
+impl<A: QBotAuthorizer + Sync> QBotApiClientImpl<A> {
+    async fn send_authorized<T: DeserializeOwned>(
+        &self,
+        req: reqwest::RequestBuilder,
+    ) -> QBotApiResult<T> {
+        req.with_access_token(&self.authorizer)
+            .await?
+            .send_timed()
+            .await?
+            .to_qbot_result()
+            .await
+    }
+
+    async fn send_reauthorized<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        build: &impl Fn() -> reqwest::RequestBuilder,
+    ) -> QBotApiResult<T> {
+        match self.send_authorized(build()).await {
+            Err(QBotApiError::ApiError {
+                status_code: 401,
+                code,
+                message,
+                ..
+            }) => {
+                warn!(
+                    "{} unauthorized ({} {}), refreshing access token and retrying",
+                    endpoint, code, message
+                );
+                self.authorizer.invalidate().await;
+                self.send_authorized(build()).await
+            }
+            res => res,
+        }
+    }
+
+    async fn set_member_mute(
+        &self,
+        endpoint: &'static str,
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> QBotApiResult<()> {
+        #[derive(Serialize)]
+        struct MuteMemberRequest {
+            mute_end_timestamp: String,
+        }
+
+        let mute_end_timestamp = mute_end_timestamp.to_string();
+        self.request(endpoint, || {
+            self.client
+                .patch(format!(
+                    "{}/guilds/{guild_id}/members/{user_id}/mute",
+                    self.base_url
+                ))
+                .json(&MuteMemberRequest {
+                    mute_end_timestamp: mute_end_timestamp.clone(),
+                })
+        })
+        .await
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> QBotApiResult<T> {
+        let policy = RetryPolicy {
+            max_retries: self.max_retries,
+            retry_request_errors: true,
+        };
+        self.request_with_retry(endpoint, policy, build).await
+    }
+
+    async fn request_with_retry<T: DeserializeOwned>(
+        &self,
+        endpoint: &'static str,
+        policy: RetryPolicy,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> QBotApiResult<T> {
+        let mut res = self.send_reauthorized(endpoint, &build).await;
+        for attempt in 1..=policy.max_retries {
+            let retryable = match &res {
+                Err(QBotApiError::RequestError(_)) => policy.retry_request_errors,
+                // Nothing was sent, or QQ rejected it, so retrying is safe.
+                Err(e @ (QBotApiError::ApiError { .. } | QBotApiError::AccessTokenError(_))) => {
+                    e.is_transient()
+                }
+                Err(QBotApiError::InvalidAppId(_)) => false,
+                Ok(_) => false,
+            };
+            if !retryable {
+                break;
+            }
+            warn!(
+                "{} failed ({}), retrying ({}/{})",
+                endpoint,
+                res.as_ref().err().unwrap(),
+                attempt,
+                policy.max_retries
+            );
+            tokio::time::sleep(retry_delay(attempt)).await;
+            res = self.send_reauthorized(endpoint, &build).await;
         }
+        let outcome = match &res {
+            Ok(_) => ApiCallOutcome::Success,
+            Err(QBotApiError::ApiError { .. }) => ApiCallOutcome::ApiError,
+            Err(QBotApiError::RequestError(_) | QBotApiError::InvalidAppId(_)) => {
+                ApiCallOutcome::RequestError
+            }
+            Err(QBotApiError::AccessTokenError(_)) => ApiCallOutcome::AccessTokenError,
+        };
+        self.metrics.record(endpoint, outcome);
+        res
     }
 }
 
-trait WithAccessToken {
-    async fn with_access_token(self, authorizer: impl QBotAuthorizer) -> Self;
+trait WithAccessToken: Sized {
+    async fn with_access_token(self, authorizer: impl QBotAuthorizer) -> QBotApiResult<Self>;
 }
 
 impl WithAccessToken for reqwest::RequestBuilder {
-    async fn with_access_token(self, authorizer: impl QBotAuthorizer) -> Self {
-        let access_token = authorizer.get_access_token().await.unwrap();
-        self.header("Authorization", format!("QQBot {access_token}"))
+    async fn with_access_token(self, authorizer: impl QBotAuthorizer) -> QBotApiResult<Self> {
+        let access_token = authorizer
+            .get_access_token()
+            .await
+            .map_err(|e| QBotApiError::AccessTokenError(Box::new(e)))?;
+        Ok(self.header("Authorization", format!("QQBot {access_token}")))
+    }
+}
+
+trait SendTimed {
+    async fn send_timed(self) -> reqwest::Result<reqwest::Response>;
+}
+
+impl SendTimed for reqwest::RequestBuilder {
+    async fn send_timed(self) -> reqwest::Result<reqwest::Response> {
+        let started_at = Instant::now();
+        let res = self.send().await;
+        METRICS.api_call_duration.observe(started_at.elapsed());
+        res
     }
 }
 
 impl<A: QBotAuthorizer + Sync> QBotApiClient for QBotApiClientImpl<A> {
     async fn reply_text_to_channel_message(
         &self,
-        message_id: &str,
-        channel_id: &str,
+        message_id: &MessageId,
+        msg_seq: u32,
+        channel_id: &ChannelId,
         content: &str,
     ) -> QBotApiResult<()> {
         #[derive(Serialize)]
         struct ReplyTextRequest<'a> {
             msg_id: &'a str,
+            msg_seq: u32,
             content: &'a str,
         }
         #[derive(Deserialize)]
         struct ReplyTextResponse {}
 
+        // Retries below reuse the same msg_seq, so a reply that did go through is not repeated.
         let _res: ReplyTextResponse = self
-            .client
-            .post(&format!("{}/channels/{channel_id}/messages", self.base_url))
-            .with_access_token(&self.authorizer)
-            .await
-            .json(&ReplyTextRequest {
-                msg_id: message_id,
-                content,
+            .request("reply_text_to_channel_message", || {
+                self.client
+                    .post(format!("{}/channels/{channel_id}/messages", self.base_url))
+                    .json(&ReplyTextRequest {
+                        msg_id: message_id,
+                        msg_seq,
+                        content,
+                    })
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn send_text_to_channel(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> QBotApiResult<()> {
+        #[derive(Serialize)]
+        struct SendTextRequest<'a> {
+            content: &'a str,
+        }
+        #[derive(Deserialize)]
+        struct SendTextResponse {}
+
+        let policy = RetryPolicy {
+            max_retries: self.max_retries,
+            retry_request_errors: false,
+        };
+        let _res: SendTextResponse = self
+            .request_with_retry("send_text_to_channel", policy, || {
+                self.client
+                    .post(format!("{}/channels/{channel_id}/messages", self.base_url))
+                    .json(&SendTextRequest { content })
             })
-            .send()
-            .await?
-            .to_qbot_result()
             .await?;
         Ok(())
     }
 
     async fn send_channel_thread_html(
         &self,
-        channel_id: &str,
+        channel_id: &ChannelId,
         title: &str,
         html: &str,
-    ) -> QBotApiResult<()> {
+    ) -> QBotApiResult<String> {
         #[derive(Serialize)]
         struct SendChannelThreadHtmlRequest<'a> {
             title: &'a str,
@@ -109,106 +486,259 @@ impl<A: QBotAuthorizer + Sync> QBotApiClient for QBotApiClientImpl<A> {
             format: u32,
         }
         #[derive(Debug, Deserialize)]
-        #[allow(dead_code)]
         struct SendChannelThreadHtmlResponse {
             task_id: String,
+            #[allow(dead_code)]
             create_time: String,
         }
 
+        let policy = RetryPolicy {
+            max_retries: self.thread_max_retries,
+            retry_request_errors: false,
+        };
         let res: SendChannelThreadHtmlResponse = self
-            .client
-            .put(&format!("{}/channels/{channel_id}/threads", self.base_url))
-            .with_access_token(&self.authorizer)
-            .await
-            .json(&SendChannelThreadHtmlRequest {
-                title,
-                content: html,
-                format: 2,
+            .request_with_retry("send_channel_thread_html", policy, || {
+                self.client
+                    .put(format!("{}/channels/{channel_id}/threads", self.base_url))
+                    .timeout(self.thread_timeout)
+                    .json(&SendChannelThreadHtmlRequest {
+                        title,
+                        content: html,
+                        format: 2,
+                    })
             })
-            .send()
-            .await?
-            .to_qbot_result()
             .await?;
         debug!(thread_sent=?res, "thread sent");
-        Ok(())
+        Ok(res.task_id)
     }
 
-    fn list_channels(
+    async fn mute_member(
         &self,
-        guild_id: &str,
-    ) -> impl Future<Output = QBotApiResult<Vec<model::Channel>>> + Send {
-        async move {
-            let res = self
-                .client
-                .get(&format!("{}/guilds/{guild_id}/channels", self.base_url))
-                .with_access_token(&self.authorizer)
-                .await
-                .send()
-                .await?
-                .to_qbot_result()
-                .await?;
-            Ok(res)
-        }
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> QBotApiResult<()> {
+        self.set_member_mute("mute_member", guild_id, user_id, mute_end_timestamp)
+            .await
+    }
+
+    async fn unmute_member(&self, guild_id: &GuildId, user_id: &str) -> QBotApiResult<()> {
+        // QQ lifts the mute when the end timestamp is 0.
+        self.set_member_mute("unmute_member", guild_id, user_id, 0)
+            .await
+    }
+
+    async fn list_threads(&self, channel_id: &ChannelId) -> QBotApiResult<Vec<model::Thread>> {
+        let res: model::ChannelThreads = self
+            .request("list_threads", || {
+                self.client
+                    .get(format!("{}/channels/{channel_id}/threads", self.base_url))
+            })
+            .await?;
+        Ok(res.threads)
+    }
+
+    async fn delete_thread(&self, channel_id: &ChannelId, thread_id: &str) -> QBotApiResult<()> {
+        self.request("delete_thread", || {
+            self.client.delete(format!(
+                "{}/channels/{channel_id}/threads/{thread_id}",
+                self.base_url
+            ))
+        })
+        .await
+    }
+
+    async fn list_channels(&self, guild_id: &GuildId) -> QBotApiResult<Vec<model::Channel>> {
+        let res = self
+            .request("list_channels", || {
+                self.client
+                    .get(format!("{}/guilds/{guild_id}/channels", self.base_url))
+            })
+            .await?;
+        Ok(res)
+    }
+
+    async fn get_me(&self) -> QBotApiResult<model::User> {
+        self.request("get_me", || {
+            self.client.get(format!("{}/users/@me", self.base_url))
+        })
+        .await
+    }
+
+    async fn get_channel(&self, channel_id: &ChannelId) -> QBotApiResult<model::Channel> {
+        self.request("get_channel", || {
+            self.client
+                .get(format!("{}/channels/{channel_id}", self.base_url))
+        })
+        .await
+    }
+
+    async fn list_guild_roles(&self, guild_id: &GuildId) -> QBotApiResult<Vec<model::Role>> {
+        let res: model::GuildRoles = self
+            .request("list_guild_roles", || {
+                self.client
+                    .get(format!("{}/guilds/{guild_id}/roles", self.base_url))
+            })
+            .await?;
+        Ok(res.roles)
     }
 }
 
 impl<A: QBotApiClient + Sync> QBotApiClient for &A {
     async fn reply_text_to_channel_message(
         &self,
-        message_id: &str,
-        channel_id: &str,
+        message_id: &MessageId,
+        msg_seq: u32,
+        channel_id: &ChannelId,
         content: &str,
     ) -> QBotApiResult<()> {
         (*self)
-            .reply_text_to_channel_message(message_id, channel_id, content)
+            .reply_text_to_channel_message(message_id, msg_seq, channel_id, content)
             .await
     }
     async fn send_channel_thread_html(
         &self,
-        channel_id: &str,
+        channel_id: &ChannelId,
         title: &str,
         html: &str,
-    ) -> QBotApiResult<()> {
+    ) -> QBotApiResult<String> {
         (*self)
             .send_channel_thread_html(channel_id, title, html)
             .await
     }
+    async fn delete_thread(&self, channel_id: &ChannelId, thread_id: &str) -> QBotApiResult<()> {
+        (*self).delete_thread(channel_id, thread_id).await
+    }
+    async fn send_text_to_channel(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> QBotApiResult<()> {
+        (*self).send_text_to_channel(channel_id, content).await
+    }
+    async fn mute_member(
+        &self,
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> QBotApiResult<()> {
+        (*self)
+            .mute_member(guild_id, user_id, mute_end_timestamp)
+            .await
+    }
+    async fn unmute_member(&self, guild_id: &GuildId, user_id: &str) -> QBotApiResult<()> {
+        (*self).unmute_member(guild_id, user_id).await
+    }
+
+    fn get_me(&self) -> impl Future<Output = QBotApiResult<model::User>> + Send {
+        (*self).get_me()
+    }
 
     fn list_channels(
         &self,
-        guild_id: &str,
+        guild_id: &GuildId,
     ) -> impl Future<Output = QBotApiResult<Vec<model::Channel>>> + Send {
         (*self).list_channels(guild_id)
     }
+
+    fn get_channel(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<model::Channel>> + Send {
+        (*self).get_channel(channel_id)
+    }
+
+    fn list_guild_roles(
+        &self,
+        guild_id: &GuildId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Role>>> + Send {
+        (*self).list_guild_roles(guild_id)
+    }
+
+    fn list_threads(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Thread>>> + Send {
+        (*self).list_threads(channel_id)
+    }
 }
 impl<A: QBotApiClient + Send + Sync> QBotApiClient for std::sync::Arc<A> {
     async fn reply_text_to_channel_message(
         &self,
-        message_id: &str,
-        channel_id: &str,
+        message_id: &MessageId,
+        msg_seq: u32,
+        channel_id: &ChannelId,
         content: &str,
     ) -> QBotApiResult<()> {
         (**self)
-            .reply_text_to_channel_message(message_id, channel_id, content)
+            .reply_text_to_channel_message(message_id, msg_seq, channel_id, content)
             .await
     }
     async fn send_channel_thread_html(
         &self,
-        channel_id: &str,
+        channel_id: &ChannelId,
         title: &str,
         html: &str,
-    ) -> QBotApiResult<()> {
+    ) -> QBotApiResult<String> {
         (**self)
             .send_channel_thread_html(channel_id, title, html)
             .await
     }
+    async fn delete_thread(&self, channel_id: &ChannelId, thread_id: &str) -> QBotApiResult<()> {
+        (**self).delete_thread(channel_id, thread_id).await
+    }
+    async fn send_text_to_channel(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> QBotApiResult<()> {
+        (**self).send_text_to_channel(channel_id, content).await
+    }
+    async fn mute_member(
+        &self,
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> QBotApiResult<()> {
+        (**self)
+            .mute_member(guild_id, user_id, mute_end_timestamp)
+            .await
+    }
+    async fn unmute_member(&self, guild_id: &GuildId, user_id: &str) -> QBotApiResult<()> {
+        (**self).unmute_member(guild_id, user_id).await
+    }
+
+    fn get_me(&self) -> impl Future<Output = QBotApiResult<model::User>> + Send {
+        (**self).get_me()
+    }
 
     fn list_channels(
         &self,
-        guild_id: &str,
+        guild_id: &GuildId,
     ) -> impl Future<Output = QBotApiResult<Vec<model::Channel>>> + Send {
         (**self).list_channels(guild_id)
     }
+
+    fn get_channel(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<model::Channel>> + Send {
+        (**self).get_channel(channel_id)
+    }
+
+    fn list_guild_roles(
+        &self,
+        guild_id: &GuildId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Role>>> + Send {
+        (**self).list_guild_roles(guild_id)
+    }
+
+    fn list_threads(
+        &self,
+        channel_id: &ChannelId,
+    ) -> impl Future<Output = QBotApiResult<Vec<model::Thread>>> + Send {
+        (**self).list_threads(channel_id)
+    }
 }
 
 impl<A: QBotAuthorizer + Sync> QBotApiClientImpl<A> {
@@ -218,13 +748,9 @@ impl<A: QBotAuthorizer + Sync> QBotApiClientImpl<A> {
             url: String,
         }
         let res: GetGatewayResponse = self
-            .client
-            .get(&format!("{}/gateway", self.base_url))
-            .with_access_token(&self.authorizer)
-            .await
-            .send()
-            .await?
-            .to_qbot_result()
+            .request("get_ws_gateway", || {
+                self.client.get(format!("{}/gateway", self.base_url))
+            })
             .await?;
         Ok(res.url)
     }
@@ -235,10 +761,49 @@ mod tests {
     use mockito::Server;
     use serde_json::json;
 
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use crate::qbot::authorizer::MockAuthorizer;
 
     use super::*;
 
+    #[derive(Default)]
+    struct RotatingAuthorizer(AtomicUsize);
+
+    impl QBotAuthorizer for RotatingAuthorizer {
+        async fn get_access_token(&self) -> QBotApiResult<String> {
+            Ok(format!("accessToken{}", self.0.load(Ordering::SeqCst)))
+        }
+        async fn invalidate(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // Hands out a token until invalidated, after which refreshing it fails.
+    #[derive(Default)]
+    struct FailingRefreshAuthorizer(AtomicUsize);
+
+    impl QBotAuthorizer for FailingRefreshAuthorizer {
+        async fn get_access_token(&self) -> QBotApiResult<String> {
+            if self.0.load(Ordering::SeqCst) == 0 {
+                return Ok("accessToken".into());
+            }
+            Err(QBotApiError::ApiError {
+                status_code: 429,
+                code: 100017,
+                message: "rate limited".into(),
+                trace_id: String::new(),
+            })
+        }
+        async fn invalidate(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn unauthorized_body() -> String {
+        json!({ "code": 11244, "message": "token not exist or expire" }).to_string()
+    }
+
     #[tokio::test]
     async fn test_get_ws_gateway() {
         let mut mock_server = Server::new_async().await;
@@ -270,6 +835,7 @@ mod tests {
             .match_header("content-type", "application/json")
             .match_body(mockito::Matcher::Json(json!({
                 "msg_id": "messageId",
+                "msg_seq": 1,
                 "content": "content",
             })))
             .with_header("content-type", "application/json")
@@ -282,9 +848,803 @@ mod tests {
             MockAuthorizer("accessToken".into()),
         );
         client
-            .reply_text_to_channel_message("messageId", "channelId", "content")
+            .reply_text_to_channel_message(&"messageId".into(), 1, &"channelId".into(), "content")
             .await
             .unwrap();
         mock.assert_async().await;
     }
+
+    #[tokio::test]
+    async fn test_reply_msg_seq_sent() {
+        let mut mock_server = Server::new_async().await;
+        let mut mocks = vec![];
+        for (message_id, msg_seq) in [("messageId", 1), ("messageId", 2), ("otherId", 1)] {
+            let mock = mock_server
+                .mock("POST", "/channels/channelId/messages")
+                .match_body(mockito::Matcher::Json(json!({
+                    "msg_id": message_id,
+                    "msg_seq": msg_seq,
+                    "content": "content",
+                })))
+                .with_header("content-type", "application/json")
+                .with_body(json!({}).to_string())
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        for (message_id, msg_seq) in [("messageId", 1), ("messageId", 2), ("otherId", 1)] {
+            client
+                .reply_text_to_channel_message(
+                    &message_id.into(),
+                    msg_seq,
+                    &"channelId".into(),
+                    "content",
+                )
+                .await
+                .unwrap();
+        }
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_text_to_channel() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("POST", "/channels/channelId/messages")
+            .match_header("X-Union-Appid", "appId")
+            .match_header("Authorization", "QQBot accessToken")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::Json(json!({ "content": "content" })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({}).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        client
+            .send_text_to_channel(&"channelId".into(), "content")
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_text_not_retried_on_timeout() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("POST", "/channels/channelId/messages")
+            .with_header("content-type", "application/json")
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(b"{}")
+            })
+            .expect(1)
+            .create_async()
+            .await;
+        let client = QBotApiClientBuilder::new()
+            .base_url(mock_server.url())
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .timeout(Duration::from_millis(100))
+            .retry(2)
+            .build()
+            .unwrap();
+        let err = client
+            .send_text_to_channel(&"channelId".into(), "content")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, QBotApiError::RequestError(e) if e.is_timeout()),
+            "{err:?}"
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_text_retried_on_rate_limit() {
+        let mut mock_server = Server::new_async().await;
+        let rate_limited_mock = mock_server
+            .mock("POST", "/channels/channelId/messages")
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 100017, "message": "rate limited" }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let ok_mock = mock_server
+            .mock("POST", "/channels/channelId/messages")
+            .with_header("content-type", "application/json")
+            .with_body(json!({}).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = QBotApiClientBuilder::new()
+            .base_url(mock_server.url())
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .retry(1)
+            .build()
+            .unwrap();
+        client
+            .send_text_to_channel(&"channelId".into(), "content")
+            .await
+            .unwrap();
+        rate_limited_mock.assert_async().await;
+        ok_mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_millis(500));
+        assert_eq!(retry_delay(2), Duration::from_secs(1));
+        assert_eq!(retry_delay(7), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(33), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_builder_invalid_app_id() {
+        let res = QBotApiClientBuilder::new()
+            .app_id("app\nId")
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .build();
+        assert!(matches!(res, Err(QBotApiError::InvalidAppId(app_id)) if app_id == "app\nId"));
+    }
+
+    #[tokio::test]
+    async fn test_builder_config() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/channels/channelId")
+            .match_header("X-Union-Appid", "customAppId")
+            .match_header("User-Agent", "rust-cn-qbot/test")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_header("content-type", "application/json")
+            .with_chunked_body(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(b"{}")
+            })
+            .expect(2)
+            .create_async()
+            .await;
+        let client = QBotApiClientBuilder::new()
+            .base_url(mock_server.url())
+            .app_id("customAppId")
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .user_agent("rust-cn-qbot/test")
+            .timeout(Duration::from_millis(100))
+            .retry(1)
+            .build()
+            .unwrap();
+        let err = client.get_channel(&"channelId".into()).await.unwrap_err();
+        assert!(
+            matches!(&err, QBotApiError::RequestError(e) if e.is_timeout()),
+            "{err:?}"
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_thread_timeout() {
+        let mut mock_server = Server::new_async().await;
+        let slow_body = |w: &mut dyn std::io::Write| {
+            std::thread::sleep(Duration::from_millis(300));
+            w.write_all(
+                json!({ "task_id": "taskId", "create_time": "0" })
+                    .to_string()
+                    .as_bytes(),
+            )
+        };
+        let thread_mock = mock_server
+            .mock("PUT", "/channels/channelId/threads")
+            .with_header("content-type", "application/json")
+            .with_chunked_body(slow_body)
+            .create_async()
+            .await;
+        let channel_mock = mock_server
+            .mock("GET", "/channels/channelId")
+            .with_header("content-type", "application/json")
+            .with_chunked_body(slow_body)
+            .create_async()
+            .await;
+        let client = QBotApiClientBuilder::new()
+            .base_url(mock_server.url())
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .timeout(Duration::from_millis(100))
+            .thread_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+
+        let task_id = client
+            .send_channel_thread_html(&"channelId".into(), "title", "<p>content</p>")
+            .await
+            .unwrap();
+        assert_eq!(task_id, "taskId");
+        let err = client.get_channel(&"channelId".into()).await.unwrap_err();
+        assert!(
+            matches!(&err, QBotApiError::RequestError(e) if e.is_timeout()),
+            "{err:?}"
+        );
+        thread_mock.assert_async().await;
+        channel_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_me() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/users/@me")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({ "id": "botId", "username": "rust-cn-qbot", "avatar": "", "union_openid": "" })
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let me = client.get_me().await.unwrap();
+        assert_eq!(
+            me,
+            model::User {
+                id: "botId".into(),
+                username: "rust-cn-qbot".into(),
+            }
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_channel() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/channels/channelId")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "id": "channelId",
+                    "guild_id": "guildId",
+                    "name": "Rust日报",
+                    "type": 10007,
+                    "position": 1,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let channel = client.get_channel(&"channelId".into()).await.unwrap();
+        assert_eq!(
+            channel,
+            model::Channel {
+                id: "channelId".into(),
+                guild_id: "guildId".into(),
+                name: "Rust日报".into(),
+                channel_type: model::Channel::TYPE_FORUM,
+            }
+        );
+        assert!(channel.is_forum());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_channel_not_found() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/channels/missingId")
+            .with_status(404)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 11263, "message": "channel not found" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let err = client.get_channel(&"missingId".into()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 404,
+                code: 11263,
+                ..
+            }
+        ));
+        assert_eq!(
+            client
+                .metrics()
+                .get("get_channel", ApiCallOutcome::ApiError),
+            1
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_guild_roles() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/guilds/guildId/roles")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "guild_id": "guildId",
+                    "roles": [
+                        { "id": "4", "name": "创建者", "color": 4294917938u32, "hoist": 1 },
+                        { "id": "10001", "name": "日报管理员", "color": 4294927682u32, "hoist": 0 },
+                    ],
+                    "role_num_limit": "32",
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let roles = client.list_guild_roles(&"guildId".into()).await.unwrap();
+        assert_eq!(
+            roles,
+            [
+                model::Role {
+                    id: "4".into(),
+                    name: "创建者".into(),
+                    color: 4294917938,
+                },
+                model::Role {
+                    id: "10001".into(),
+                    name: "日报管理员".into(),
+                    color: 4294927682,
+                },
+            ]
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_guild_roles_forbidden() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/guilds/guildId/roles")
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 11264, "message": "no permission" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let err = client
+            .list_guild_roles(&"guildId".into())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 403,
+                code: 11264,
+                ..
+            }
+        ));
+        assert_eq!(
+            client
+                .metrics()
+                .get("list_guild_roles", ApiCallOutcome::ApiError),
+            1
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_channel_thread_html() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("PUT", "/channels/channelId/threads")
+            .match_header("Authorization", "QQBot accessToken")
+            .match_body(mockito::Matcher::Json(json!({
+                "title": "title",
+                "content": "<p>content</p>",
+                "format": 2,
+            })))
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "task_id": "taskId", "create_time": "1712800000" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let task_id = client
+            .send_channel_thread_html(&"channelId".into(), "title", "<p>content</p>")
+            .await
+            .unwrap();
+        assert_eq!(task_id, "taskId");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_threads() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/channels/channelId/threads")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "threads": [{
+                        "guild_id": "guildId",
+                        "channel_id": "channelId",
+                        "author_id": "botId",
+                        "thread_info": {
+                            "thread_id": "threadId",
+                            "title": "title",
+                            "content": "{}",
+                            "date_time": "2024-04-11T00:00:00+08:00",
+                        },
+                    }],
+                    "is_finish": 1,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let threads = client.list_threads(&"channelId".into()).await.unwrap();
+        assert_eq!(
+            threads,
+            [model::Thread {
+                author_id: "botId".into(),
+                thread_info: model::ThreadInfo {
+                    thread_id: "threadId".into(),
+                    title: "title".into(),
+                },
+            }]
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_thread_retried_on_server_error() {
+        let mut mock_server = Server::new_async().await;
+        let unavailable_mock = mock_server
+            .mock("PUT", "/channels/channelId/threads")
+            .with_status(503)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 503, "message": "service unavailable" }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let ok_mock = mock_server
+            .mock("PUT", "/channels/channelId/threads")
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "task_id": "taskId", "create_time": "1712800000" }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+
+        let task_id = client
+            .send_channel_thread_html(&"channelId".into(), "title", "<p>content</p>")
+            .await
+            .unwrap();
+        assert_eq!(task_id, "taskId");
+        unavailable_mock.assert_async().await;
+        ok_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_thread_not_retried_on_client_error() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("PUT", "/channels/channelId/threads")
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 11264, "message": "no permission" }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = QBotApiClientBuilder::new()
+            .base_url(mock_server.url())
+            .authorizer(MockAuthorizer("accessToken".into()))
+            .thread_retry(3)
+            .build()
+            .unwrap();
+
+        let err = client
+            .send_channel_thread_html(&"channelId".into(), "title", "<p>content</p>")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 403,
+                ..
+            }
+        ));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_thread() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("DELETE", "/channels/channelId/threads/threadId")
+            .match_header("Authorization", "QQBot accessToken")
+            .with_status(204)
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        client
+            .delete_thread(&"channelId".into(), "threadId")
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .metrics()
+                .get("delete_thread", ApiCallOutcome::Success),
+            1
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_mute_member() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("PATCH", "/guilds/guildId/members/userId/mute")
+            .match_header("Authorization", "QQBot accessToken")
+            .match_body(mockito::Matcher::Json(
+                json!({ "mute_end_timestamp": "1713024990" }),
+            ))
+            .with_status(204)
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        client
+            .mute_member(&"guildId".into(), "userId", 1713024990)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.metrics().get("mute_member", ApiCallOutcome::Success),
+            1
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_unmute_member() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("PATCH", "/guilds/guildId/members/userId/mute")
+            .match_body(mockito::Matcher::Json(json!({ "mute_end_timestamp": "0" })))
+            .with_status(204)
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        client
+            .unmute_member(&"guildId".into(), "userId")
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_mute_member_permission_denied() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("PATCH", "/guilds/guildId/members/userId/mute")
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 50013, "message": "no permission" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let err = client
+            .mute_member(&"guildId".into(), "userId", 1713024990)
+            .await
+            .unwrap_err();
+        assert!(err.is_permission_denied());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_thread_not_found() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("DELETE", "/channels/channelId/threads/missingId")
+            .with_status(404)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 304003, "message": "thread not found" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let err = client
+            .delete_thread(&"channelId".into(), "missingId")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 404,
+                code: 304003,
+                ..
+            }
+        ));
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_api_error_counted() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .with_status(500)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "code": 500, "message": "internal error" }).to_string())
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            MockAuthorizer("accessToken".into()),
+        );
+        let err = client.list_channels(&"guildId".into()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 500,
+                ..
+            }
+        ));
+        let metrics = client.metrics();
+        assert_eq!(metrics.get("list_channels", ApiCallOutcome::ApiError), 1);
+        assert_eq!(metrics.get("list_channels", ApiCallOutcome::Success), 0);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_once_on_unauthorized() {
+        let mut mock_server = Server::new_async().await;
+        let mock_unauthorized = mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .match_header("Authorization", "QQBot accessToken0")
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(unauthorized_body())
+            .create_async()
+            .await;
+        let mock_ok = mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .match_header("Authorization", "QQBot accessToken1")
+            .with_header("content-type", "application/json")
+            .with_body(json!([{ "id": "1", "guild_id": "guildId", "name": "name" }]).to_string())
+            .create_async()
+            .await;
+        let client =
+            QBotApiClientImpl::new(mock_server.url(), "appId", RotatingAuthorizer::default());
+        let channels = client.list_channels(&"guildId".into()).await.unwrap();
+        assert_eq!(channels.len(), 1);
+        assert_eq!(client.authorizer.0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            client
+                .metrics()
+                .get("list_channels", ApiCallOutcome::Success),
+            1
+        );
+        mock_unauthorized.assert_async().await;
+        mock_ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_failure_after_unauthorized() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(unauthorized_body())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = QBotApiClientImpl::new(
+            mock_server.url(),
+            "appId",
+            FailingRefreshAuthorizer::default(),
+        );
+        let err = client.list_channels(&"guildId".into()).await.unwrap_err();
+        match &err {
+            QBotApiError::AccessTokenError(e) => assert!(matches!(
+                **e,
+                QBotApiError::ApiError {
+                    status_code: 429,
+                    ..
+                }
+            )),
+            e => panic!("unexpected error: {e:?}"),
+        }
+        assert!(err.is_transient());
+        assert_eq!(
+            client
+                .metrics()
+                .get("list_channels", ApiCallOutcome::AccessTokenError),
+            1
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_unauthorized_only_once() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("GET", "/guilds/guildId/channels")
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(unauthorized_body())
+            .expect(2)
+            .create_async()
+            .await;
+        let client =
+            QBotApiClientImpl::new(mock_server.url(), "appId", RotatingAuthorizer::default());
+        let err = client.list_channels(&"guildId".into()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            QBotApiError::ApiError {
+                status_code: 401,
+                ..
+            }
+        ));
+        assert_eq!(client.authorizer.0.load(Ordering::SeqCst), 1);
+        mock.assert_async().await;
+    }
 }
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use super::model::{Channel, Role, Thread, ThreadInfo, User};
+use super::QBotApiClient;
+use crate::qbot::{ChannelId, GuildId, MessageId, QBotApiError, QBotApiResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SentMessage {
+    Reply {
+        message_id: MessageId,
+        msg_seq: u32,
+        channel_id: ChannelId,
+        content: String,
+    },
+    Thread {
+        channel_id: ChannelId,
+        thread_id: String,
+        title: String,
+        html: String,
+    },
+    ThreadDeleted {
+        channel_id: ChannelId,
+        thread_id: String,
+    },
+    Text {
+        channel_id: ChannelId,
+        content: String,
+    },
+}
+
+/// A [`QBotApiClient`] that records everything sent through it instead of calling QQ. Channels
+/// have to be added up front; looking up any other channel fails like it would on QQ. Like QQ,
+/// sending a thread returns a task id rather than the thread id.
+#[derive(Default)]
+pub struct InMemoryApiClient {
+    channels: Mutex<Vec<Channel>>,
+    roles: Mutex<Vec<(GuildId, Role)>>,
+    mutes: Mutex<HashMap<(GuildId, String), u64>>,
+    sent: Mutex<Vec<SentMessage>>,
+    fail_replies: AtomicBool,
+}
+
+impl InMemoryApiClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_channel(&self, channel: Channel) {
+        self.channels.lock().unwrap().push(channel);
+    }
+
+    pub fn add_role(&self, guild_id: GuildId, role: Role) {
+        self.roles.lock().unwrap().push((guild_id, role));
+    }
+
+    /// Makes replies fail with an internal server error instead of being recorded.
+    pub fn fail_replies(&self) {
+        self.fail_replies.store(true, Ordering::SeqCst);
+    }
+
+    /// Everything sent so far, in order.
+    pub fn sent(&self) -> Vec<SentMessage> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// `(channel_id, title, html)` of the threads sent so far and not deleted since.
+    pub fn threads(&self) -> Vec<(ChannelId, String, String)> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter()
+            .filter_map(|message| match message {
+                SentMessage::Thread {
+                    channel_id,
+                    thread_id,
+                    title,
+                    html,
+                } if !Self::is_deleted(&sent, thread_id) => {
+                    Some((channel_id.clone(), title.clone(), html.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Contents of the replies sent so far.
+    pub fn replies(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|sent| match sent {
+                SentMessage::Reply { content, .. } => Some(content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// When the member's mute ends, or `None` if they aren't muted.
+    pub fn mute_end_timestamp(&self, guild_id: &GuildId, user_id: &str) -> Option<u64> {
+        self.mutes
+            .lock()
+            .unwrap()
+            .get(&(guild_id.clone(), user_id.into()))
+            .copied()
+    }
+
+    fn record(&self, sent: SentMessage) -> QBotApiResult<()> {
+        self.sent.lock().unwrap().push(sent);
+        Ok(())
+    }
+
+    fn is_deleted(sent: &[SentMessage], id: &str) -> bool {
+        sent.iter().any(|message| {
+            matches!(message, SentMessage::ThreadDeleted { thread_id, .. } if thread_id == id)
+        })
+    }
+
+    fn not_found(message: &str) -> QBotApiError {
+        QBotApiError::ApiError {
+            status_code: 404,
+            code: 11263,
+            message: message.into(),
+            trace_id: String::new(),
+        }
+    }
+}
+
+impl QBotApiClient for InMemoryApiClient {
+    async fn get_me(&self) -> QBotApiResult<User> {
+        Ok(User {
+            id: "botId".into(),
+            username: "rust-cn-qbot".into(),
+        })
+    }
+
+    async fn list_channels(&self, guild_id: &GuildId) -> QBotApiResult<Vec<Channel>> {
+        Ok(self
+            .channels
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|channel| channel.guild_id == *guild_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_channel(&self, channel_id: &ChannelId) -> QBotApiResult<Channel> {
+        self.channels
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|channel| channel.id == *channel_id)
+            .cloned()
+            .ok_or_else(|| Self::not_found("channel not found"))
+    }
+
+    async fn list_guild_roles(&self, guild_id: &GuildId) -> QBotApiResult<Vec<Role>> {
+        Ok(self
+            .roles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(role_guild_id, _)| role_guild_id == guild_id)
+            .map(|(_, role)| role.clone())
+            .collect())
+    }
+
+    async fn reply_text_to_channel_message(
+        &self,
+        message_id: &MessageId,
+        msg_seq: u32,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> QBotApiResult<()> {
+        if self.fail_replies.load(Ordering::SeqCst) {
+            return Err(QBotApiError::ApiError {
+                status_code: 500,
+                code: 500000,
+                message: "internal error".into(),
+                trace_id: String::new(),
+            });
+        }
+        self.record(SentMessage::Reply {
+            message_id: message_id.clone(),
+            msg_seq,
+            channel_id: channel_id.clone(),
+            content: content.into(),
+        })
+    }
+
+    async fn send_channel_thread_html(
+        &self,
+        channel_id: &ChannelId,
+        title: &str,
+        html: &str,
+    ) -> QBotApiResult<String> {
+        let mut sent = self.sent.lock().unwrap();
+        let task_id = format!("task{}", sent.len());
+        let thread_id = format!("thread{}", sent.len());
+        sent.push(SentMessage::Thread {
+            channel_id: channel_id.clone(),
+            thread_id,
+            title: title.into(),
+            html: html.into(),
+        });
+        Ok(task_id)
+    }
+
+    async fn list_threads(&self, channel_id: &ChannelId) -> QBotApiResult<Vec<Thread>> {
+        let sent = self.sent.lock().unwrap();
+        Ok(sent
+            .iter()
+            .filter_map(|message| match message {
+                SentMessage::Thread {
+                    channel_id: c,
+                    thread_id,
+                    title,
+                    ..
+                } if c == channel_id && !Self::is_deleted(&sent, thread_id) => Some(Thread {
+                    author_id: "botId".into(),
+                    thread_info: ThreadInfo {
+                        thread_id: thread_id.clone(),
+                        title: title.clone(),
+                    },
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn delete_thread(&self, channel_id: &ChannelId, thread_id: &str) -> QBotApiResult<()> {
+        let mut sent = self.sent.lock().unwrap();
+        let exists = sent.iter().any(|message| {
+            matches!(
+                message,
+                SentMessage::Thread { channel_id: c, thread_id: t, .. }
+                    if c == channel_id && t == thread_id
+            )
+        });
+        if !exists || Self::is_deleted(&sent, thread_id) {
+            return Err(Self::not_found("thread not found"));
+        }
+        sent.push(SentMessage::ThreadDeleted {
+            channel_id: channel_id.clone(),
+            thread_id: thread_id.into(),
+        });
+        Ok(())
+    }
+
+    async fn send_text_to_channel(
+        &self,
+        channel_id: &ChannelId,
+        content: &str,
+    ) -> QBotApiResult<()> {
+        self.record(SentMessage::Text {
+            channel_id: channel_id.clone(),
+            content: content.into(),
+        })
+    }
+
+    async fn mute_member(
+        &self,
+        guild_id: &GuildId,
+        user_id: &str,
+        mute_end_timestamp: u64,
+    ) -> QBotApiResult<()> {
+        self.mutes
+            .lock()
+            .unwrap()
+            .insert((guild_id.clone(), user_id.into()), mute_end_timestamp);
+        Ok(())
+    }
+
+    async fn unmute_member(&self, guild_id: &GuildId, user_id: &str) -> QBotApiResult<()> {
+        self.mutes
+            .lock()
+            .unwrap()
+            .remove(&(guild_id.clone(), user_id.into()));
+        Ok(())
+    }
+}
@@ -1,8 +1,58 @@
 use serde::Deserialize;
 
+use crate::qbot::{ChannelId, GuildId};
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Channel {
+    pub id: ChannelId,
+    pub guild_id: GuildId,
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub channel_type: u32,
+}
+
+impl Channel {
+    pub const TYPE_FORUM: u32 = 10007;
+
+    pub fn is_forum(&self) -> bool {
+        self.channel_type == Self::TYPE_FORUM
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Role {
     pub id: String,
-    pub guild_id: String,
     pub name: String,
+    /// ARGB, e.g. `4294927682` for a red role.
+    #[serde(default)]
+    pub color: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct GuildRoles {
+    pub roles: Vec<Role>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Thread {
+    pub author_id: String,
+    pub thread_info: ThreadInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ThreadInfo {
+    pub thread_id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct ChannelThreads {
+    #[serde(default)]
+    pub threads: Vec<Thread>,
 }
@@ -1,5 +1,6 @@
 use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[cfg(test)]
 use mock_instant::Instant;
@@ -8,13 +9,18 @@ use std::time::Instant;
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex as TokioMutex;
+use tracing::warn;
 
 use super::error::QBotApiResultFromResponseExt;
 use super::json_u64::deserialize_json_u64;
 use super::QBotApiResult;
+use crate::metrics::METRICS;
 
 pub trait QBotAuthorizer {
     fn get_access_token(&self) -> impl Future<Output = QBotApiResult<String>> + Send;
+    fn invalidate(&self) -> impl Future<Output = ()> + Send {
+        std::future::ready(())
+    }
 }
 
 struct QBotAuthorizerImpl {
@@ -25,7 +31,7 @@ struct QBotAuthorizerImpl {
 
 pub struct QBotCachingAuthorizerImpl {
     inner: QBotAuthorizerImpl,
-    last_response: TokioMutex<(Instant, GetAccessTokenResponse)>,
+    last_response: TokioMutex<(Option<Instant>, GetAccessTokenResponse)>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -42,18 +48,48 @@ struct GetAccessTokenResponse {
     expires_in: u64,
 }
 
+// QQ rate-limits the token endpoint, and failing to get a token at startup is fatal.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+fn retry_after(res: &reqwest::Response) -> Duration {
+    res.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+        .min(MAX_RETRY_AFTER)
+}
+
 impl QBotAuthorizerImpl {
     async fn get_access_token(&self) -> QBotApiResult<GetAccessTokenResponse> {
         let client = reqwest::Client::new();
-        let res = client
-            .post(&format!("{}/app/getAppAccessToken", self.base_url))
-            .json(&GetAccessTokenRequest {
-                app_id: &self.app_id,
-                client_secret: &self.client_secret,
-            })
-            .send()
-            .await?;
-        res.to_qbot_result().await
+        let mut attempt = 0;
+        loop {
+            let res = client
+                .post(format!("{}/app/getAppAccessToken", self.base_url))
+                .json(&GetAccessTokenRequest {
+                    app_id: &self.app_id,
+                    client_secret: &self.client_secret,
+                })
+                .send()
+                .await?;
+            if res.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt == MAX_RATE_LIMIT_RETRIES
+            {
+                return res.to_qbot_result().await;
+            }
+            attempt += 1;
+            let delay = retry_after(&res);
+            warn!(
+                attempt,
+                ?delay,
+                "access token request rate limited, retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
     }
 }
 
@@ -72,7 +108,7 @@ impl QBotCachingAuthorizerImpl {
         let last_response = inner.get_access_token().await?;
         Ok(Self {
             inner,
-            last_response: TokioMutex::new((now, last_response)),
+            last_response: TokioMutex::new((Some(now), last_response)),
         })
     }
 }
@@ -89,12 +125,19 @@ impl QBotAuthorizer for QBotCachingAuthorizerImpl {
                     access_token,
                 },
             ) = &*last_response;
-            if now.duration_since(*last_requested_at).as_secs() < expires_in - 60 {
+            if last_requested_at.is_some_and(|last_requested_at| {
+                now.duration_since(last_requested_at).as_secs() < expires_in.saturating_sub(60)
+            }) {
                 return Ok(access_token.clone());
             }
-            *last_response = (now, self.inner.get_access_token().await?);
+            METRICS.token_refreshes.inc();
+            *last_response = (Some(now), self.inner.get_access_token().await?);
         }
     }
+
+    async fn invalidate(&self) {
+        self.last_response.lock().await.0 = None;
+    }
 }
 
 impl<A: QBotAuthorizer> QBotAuthorizer for Arc<A>
@@ -104,12 +147,18 @@ where
     async fn get_access_token(&self) -> QBotApiResult<String> {
         self.as_ref().get_access_token().await
     }
+    async fn invalidate(&self) {
+        self.as_ref().invalidate().await
+    }
 }
 
 impl<A: QBotAuthorizer + Sync> QBotAuthorizer for &A {
     async fn get_access_token(&self) -> QBotApiResult<String> {
         (*self).get_access_token().await
     }
+    async fn invalidate(&self) {
+        (*self).invalidate().await
+    }
 }
 
 #[cfg(test)]
@@ -118,8 +167,8 @@ pub struct MockAuthorizer(pub String);
 
 #[cfg(test)]
 impl QBotAuthorizer for MockAuthorizer {
-    fn get_access_token(&self) -> impl Future<Output = QBotApiResult<String>> + Send {
-        async move { Ok(self.0.clone()) }
+    async fn get_access_token(&self) -> QBotApiResult<String> {
+        Ok(self.0.clone())
     }
 }
 
@@ -220,6 +269,127 @@ mod tests {
         mock_refresh.assert_async().await;
     }
     #[tokio::test]
+    async fn test_refresh_invalidated_access_token() {
+        let mut mock_server = Server::new_async().await;
+        let mock_init = mock_server
+            .mock("POST", "/app/getAppAccessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "access_token": "givenAccessToken",
+                    "expires_in": "7200"
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let mock_refresh = mock_server
+            .mock("POST", "/app/getAppAccessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "access_token": "givenAccessToken2",
+                    "expires_in": "7200"
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let authorizer = QBotCachingAuthorizerImpl::create_and_authorize(
+            mock_server.url(),
+            "givenAppId".into(),
+            "givenClientSecret".into(),
+        )
+        .await;
+        let authorizer = authorizer.unwrap();
+        assert_eq!(
+            authorizer.get_access_token().await.unwrap(),
+            "givenAccessToken"
+        );
+        authorizer.invalidate().await;
+        assert_eq!(
+            authorizer.get_access_token().await.unwrap(),
+            "givenAccessToken2"
+        );
+        assert_eq!(
+            authorizer.get_access_token().await.unwrap(),
+            "givenAccessToken2"
+        );
+        mock_init.assert_async().await;
+        mock_refresh.assert_async().await;
+    }
+    #[tokio::test]
+    async fn test_retry_after_rate_limited() {
+        let mut mock_server = Server::new_async().await;
+        let mock_rate_limited = mock_server
+            .mock("POST", "/app/getAppAccessToken")
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_header("Retry-After", "1")
+            .with_body(json!({ "code": 100017, "message": "rate limited" }).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let mock_ok = mock_server
+            .mock("POST", "/app/getAppAccessToken")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "access_token": "givenAccessToken",
+                    "expires_in": "7200"
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let started_at = std::time::Instant::now();
+        let authorizer = QBotCachingAuthorizerImpl::create_and_authorize(
+            mock_server.url(),
+            "givenAppId".into(),
+            "givenClientSecret".into(),
+        )
+        .await
+        .unwrap();
+        assert!(started_at.elapsed() >= Duration::from_secs(1));
+        assert_eq!(
+            authorizer.get_access_token().await.unwrap(),
+            "givenAccessToken"
+        );
+        mock_rate_limited.assert_async().await;
+        mock_ok.assert_async().await;
+    }
+    #[tokio::test]
+    async fn test_give_up_when_still_rate_limited() {
+        let mut mock_server = Server::new_async().await;
+        let mock = mock_server
+            .mock("POST", "/app/getAppAccessToken")
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_header("Retry-After", "0")
+            .with_body(json!({ "code": 100017, "message": "rate limited" }).to_string())
+            .expect(MAX_RATE_LIMIT_RETRIES as usize + 1)
+            .create_async()
+            .await;
+
+        let res = QBotCachingAuthorizerImpl::create_and_authorize(
+            mock_server.url(),
+            "givenAppId".into(),
+            "givenClientSecret".into(),
+        )
+        .await;
+        assert!(matches!(
+            res,
+            Err(QBotApiError::ApiError {
+                status_code: 429,
+                ..
+            })
+        ));
+        mock.assert_async().await;
+    }
+    #[tokio::test]
     async fn test_get_access_token_request_error() {
         let res = QBotCachingAuthorizerImpl::create_and_authorize(
             "chipichipi".into(),
@@ -1,4 +1,5 @@
-use serde::{de::DeserializeOwned, Deserialize};
+use serde::de::{value::UnitDeserializer, DeserializeOwned};
+use serde::Deserialize;
 use thiserror::Error;
 use tokio_tungstenite::tungstenite::{error::ProtocolError, Error as WsError};
 
@@ -13,10 +14,41 @@ pub enum QBotApiError {
         message: String,
         trace_id: String,
     },
+    #[error("error getting access token: {0}")]
+    AccessTokenError(Box<QBotApiError>),
+    #[error("invalid app id {0:?}")]
+    InvalidAppId(String),
 }
 
 pub type QBotApiResult<T> = std::result::Result<T, QBotApiError>;
 
+impl QBotApiError {
+    /// Whether the bot lacks the permission for the call, e.g. muting a member without being an
+    /// admin of the guild.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(
+            self,
+            QBotApiError::ApiError {
+                status_code: 403,
+                ..
+            }
+        )
+    }
+
+    /// Whether the call may succeed if retried later, i.e. QQ was rate limiting or having
+    /// trouble rather than rejecting the request.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            QBotApiError::RequestError(_) => true,
+            QBotApiError::ApiError { status_code, .. } => {
+                *status_code == 429 || *status_code >= 500
+            }
+            QBotApiError::AccessTokenError(e) => e.is_transient(),
+            QBotApiError::InvalidAppId(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct QBotApiErrorResponse {
     #[serde(default)]
@@ -37,6 +69,8 @@ pub enum QBotWsError {
     AccessTokenError(QBotApiError),
     #[error("returned code: {0}")]
     ReturnCodeError(u32),
+    #[error("WebSocket closed by server with code {0:?}")]
+    Closed(Option<u16>),
 }
 
 pub type QBotWsResult<T> = Result<T, QBotWsError>;
@@ -49,6 +83,7 @@ impl QBotWsError {
         matches!(
             self,
             QBotWsError::ReturnCodeError(4008 | 4009)
+                | QBotWsError::Closed(Some(4008 | 4009))
                 | QBotWsError::WsError(WsError::Protocol(
                     ProtocolError::ResetWithoutClosingHandshake
                 ))
@@ -69,7 +104,7 @@ impl QBotWsError {
             QBotWsError::ReturnCodeError(_) => {
                 self.is_reidentifiable() || self.is_invalid_session()
             }
-            QBotWsError::AccessTokenError(QBotApiError::ApiError { .. }) => false,
+            QBotWsError::AccessTokenError(e) => e.is_transient(),
             _ => true,
         }
     }
@@ -82,6 +117,13 @@ pub(crate) trait QBotApiResultFromResponseExt {
 impl QBotApiResultFromResponseExt for reqwest::Response {
     async fn to_qbot_result<T: DeserializeOwned>(self) -> QBotApiResult<T> {
         let status = self.status();
+        if status == reqwest::StatusCode::NO_CONTENT {
+            // Endpoints like thread deletion succeed without a body.
+            let unit = UnitDeserializer::<serde::de::value::Error>::new();
+            if let Ok(value) = T::deserialize(unit) {
+                return Ok(value);
+            }
+        }
         if status.is_success() {
             Ok(self.json().await?)
         } else {
@@ -0,0 +1,91 @@
+use std::borrow::Borrow;
+use std::fmt::{self, Debug, Display};
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! id_type {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        // Formats like the bare string, so that ids in logs aren't wrapped in the type name.
+        impl Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(id: String) -> Self {
+                Self(id)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(id: &str) -> Self {
+                Self(id.into())
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+id_type!(
+    /// Id of a channel. Ids of different kinds don't mix:
+    ///
+    /// ```compile_fail
+    /// use rust_cn_qbot::qbot::{ChannelId, GuildId};
+    ///
+    /// fn channel_name(_channel_id: &ChannelId) {}
+    /// channel_name(&GuildId::from("guildId"));
+    /// ```
+    ChannelId
+);
+id_type!(
+    /// Id of a guild, i.e. a whole QQ 频道 rather than one of its channels.
+    GuildId
+);
+id_type!(
+    /// Id of a message.
+    MessageId
+);
@@ -1,68 +1,140 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use futures::{Sink, SinkExt, Stream, StreamExt};
-use serde::{de::DeserializeOwned, Serialize};
-use tokio::sync::{Mutex, MutexGuard};
-use tokio::{sync::Notify, time::sleep};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::sync::{mpsc, Mutex, MutexGuard};
+use tokio::time::{sleep, sleep_until, Instant};
 use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 use tracing::{debug, error, info, warn};
 
+mod events;
+mod intents;
 mod opcode;
 pub mod payload;
 
 use super::error::{QBotWsError, QBotWsResult};
 use super::QBotAuthorizer;
+use crate::metrics::METRICS;
+use events::event_handling;
+pub use events::{known_event_types, EventHandling};
+pub use intents::Intents;
 use opcode::{OpCode, OpCodePayload};
 use payload::*;
 
-#[derive(Default)]
+// Minimum time between two identifies of the same group, to stay within the identify rate limit.
+const DEFAULT_IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+// Dispatch events only save the session this often, so that a burst of events doesn't turn into
+// a burst of writes. Quitting and reconnecting always save it.
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 pub struct QBotWebSocketAuthGroup {
-    mutex: Mutex<()>,
+    // Holds the time the lock was last released.
+    mutex: Mutex<Option<Instant>>,
+    min_interval: Duration,
 }
 
 impl QBotWebSocketAuthGroup {
     pub fn new() -> Self {
+        Self::with_min_interval(DEFAULT_IDENTIFY_INTERVAL)
+    }
+
+    pub fn with_min_interval(min_interval: Duration) -> Self {
         Self {
-            mutex: Mutex::new(()),
+            mutex: Mutex::new(None),
+            min_interval,
+        }
+    }
+
+    async fn acquire(&self) -> MutexGuard<'_, Option<Instant>> {
+        let guard = self.mutex.lock().await;
+        if let Some(released_at) = *guard {
+            sleep_until(released_at + self.min_interval).await;
         }
+        guard
+    }
+}
+
+impl Default for QBotWebSocketAuthGroup {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 struct QBotWebSocketHandshaked<'g> {
-    heartbeat_interval: u64,
-    _auth_guard: MutexGuard<'g, ()>,
+    heartbeat_interval: Duration,
+    auth_guard: MutexGuard<'g, Option<Instant>>,
+}
+
+impl Drop for QBotWebSocketHandshaked<'_> {
+    fn drop(&mut self) {
+        *self.auth_guard = Some(Instant::now());
+    }
 }
 
 struct QBotWebSocketSession<S> {
     ws: S,
     session_id: String,
-    heartbeat_interval: u64,
+    heartbeat_interval: Duration,
+    heartbeat_jitter: f64,
+    next_heartbeat: Instant,
     token: String,
     last_seq: i32,
+    bot_user: ReadyUser,
+    last_saved_at: Option<Instant>,
+}
+
+async fn receive_text<
+    S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
+>(
+    ws: &mut S,
+) -> QBotWsResult<String> {
+    loop {
+        let msg = ws
+            .next()
+            .await
+            .ok_or_else(|| QBotWsError::UnexpectedData("eof".into()))??;
+        match msg {
+            WsMessage::Text(text) => break Ok(text),
+            WsMessage::Binary(data) => {
+                break String::from_utf8(data)
+                    .map_err(|_| QBotWsError::UnexpectedData("response with non-utf8".into()))
+            }
+            WsMessage::Ping(data) => {
+                debug!("received ws ping");
+                ws.send(WsMessage::Pong(data)).await?;
+            }
+            WsMessage::Pong(_) => {
+                debug!("received ws pong");
+            }
+            WsMessage::Close(frame) => {
+                info!("ws closed by server: {:?}", frame);
+                break Err(QBotWsError::Closed(frame.map(|f| f.code.into())));
+            }
+            WsMessage::Frame(_) => {}
+        }
+    }
 }
 
 async fn receive_op<
     T: DeserializeOwned + OpCodePayload + std::fmt::Debug,
-    S: Unpin + Stream<Item = Result<WsMessage, WsError>>,
+    S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
 >(
     ws: &mut S,
 ) -> QBotWsResult<QBotWebSocketPayload<T>> {
-    let msg = ws
-        .next()
-        .await
-        .ok_or_else(|| QBotWsError::UnexpectedData("eof".into()))??;
-    let msg = msg.into_data();
-    let payload = match serde_json::from_slice::<QBotWebSocketPayload<T>>(&msg) {
+    let msg = receive_text(ws).await?;
+    let payload = match serde_json::from_str::<QBotWebSocketPayload<T>>(&msg) {
         Ok(payload) => {
             debug!("received ws message: {:?}", payload);
             payload
         }
         Err(err) => {
-            error!(
-                "failed to parse ws message {}: {:?}",
-                String::from_utf8_lossy(&msg),
-                err
-            );
+            error!("failed to parse ws message {}: {:?}", msg, err);
             return Err(err.into());
         }
     };
@@ -94,19 +166,21 @@ async fn send_op<T: Serialize + OpCodePayload, S: Unpin + Sink<WsMessage, Error
 }
 
 impl<'g> QBotWebSocketHandshaked<'g> {
-    async fn handshake<S: Unpin + Stream<Item = Result<WsMessage, WsError>>>(
+    async fn handshake<
+        S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
+    >(
         ws: &mut S,
         auth_group: &'g QBotWebSocketAuthGroup,
     ) -> QBotWsResult<Self> {
-        let auth_guard = auth_group.mutex.lock().await;
+        let auth_guard = auth_group.acquire().await;
         let QBotWebSocketPayload {
             data: HelloPayload { heartbeat_interval },
             ..
         } = receive_op(ws).await?;
 
         Ok(Self {
-            heartbeat_interval,
-            _auth_guard: auth_guard,
+            heartbeat_interval: Duration::from_millis(heartbeat_interval),
+            auth_guard,
         })
     }
     async fn authenticate<
@@ -116,21 +190,17 @@ impl<'g> QBotWebSocketHandshaked<'g> {
         &self,
         authorizer: A,
         mut ws: S,
+        intents: Intents,
+        heartbeat_interval_override: Option<Duration>,
     ) -> QBotWsResult<QBotWebSocketSession<S>> {
         // Workaround for error opcode 9
         sleep(Duration::from_millis(2000)).await;
 
-        let mut token = authorizer
-            .get_access_token()
-            .await
-            .map_err(QBotWsError::AccessTokenError)?;
-        token.insert_str(0, "QQBot ");
+        let token = access_token(authorizer).await?;
 
-        const PUBLIC_GUILD_MESSAGES: u64 = 1 << 30;
-        const DIRECT_MESSAGE: u64 = 1 << 12;
         let payload = IdentifyPayload {
             token: &token,
-            intents: PUBLIC_GUILD_MESSAGES | DIRECT_MESSAGE,
+            intents: intents.into(),
             shard: (0, 1),
             properties: Default::default(),
         };
@@ -140,8 +210,12 @@ impl<'g> QBotWebSocketHandshaked<'g> {
             ws,
             session_id: Default::default(),
             heartbeat_interval: self.heartbeat_interval,
+            heartbeat_jitter: 0.0,
+            next_heartbeat: Instant::now(),
             token,
             last_seq: -1,
+            bot_user: Default::default(),
+            last_saved_at: None,
         };
         let (res_metadata, res) = session.receive_any().await?;
         if res_metadata.opcode != OpCode::OP_DISPATCH {
@@ -154,35 +228,72 @@ impl<'g> QBotWebSocketHandshaked<'g> {
             )));
         }
         let ready: QBotWebSocketPayload<ReadyPayload> = serde_json::from_slice(res.as_bytes())?;
+        if let Some(granted) = ready.data.intents {
+            let missing = intents.difference(granted);
+            if !missing.is_empty() {
+                warn!(
+                    requested = ?intents,
+                    ?granted,
+                    ?missing,
+                    "some requested intents were not granted"
+                );
+            }
+        }
         session.session_id = ready.data.session_id;
+        session.bot_user = ready.data.user;
         session.last_seq = res_metadata.seq.unwrap_or(-1);
-        // FIXME: ws get disconnected every minute. Send heartbeat every 30s as a workaround.
-        session.heartbeat_interval = 30;
+        if let Some(heartbeat_interval) = heartbeat_interval_override {
+            session.heartbeat_interval = heartbeat_interval;
+        }
 
         Ok(session)
     }
+    async fn resume_saved<
+        A: QBotAuthorizer,
+        S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
+    >(
+        &self,
+        authorizer: A,
+        ws: S,
+        saved: SavedWsSession,
+        heartbeat_interval_override: Option<Duration>,
+    ) -> QBotWsResult<QBotWebSocketSession<S>> {
+        let mut session = QBotWebSocketSession {
+            ws,
+            session_id: saved.session_id,
+            heartbeat_interval: heartbeat_interval_override.unwrap_or(self.heartbeat_interval),
+            heartbeat_jitter: 0.0,
+            next_heartbeat: Instant::now(),
+            token: access_token(authorizer).await?,
+            last_seq: saved.last_seq,
+            bot_user: saved.bot_user,
+            last_saved_at: None,
+        };
+        let payload = ResumePayload {
+            token: &session.token,
+            session_id: &session.session_id,
+            seq: session.last_seq,
+        };
+        send_op(&payload, &mut session.ws).await?;
+        Ok(session)
+    }
+}
+
+async fn access_token(authorizer: impl QBotAuthorizer) -> QBotWsResult<String> {
+    let mut token = authorizer
+        .get_access_token()
+        .await
+        .map_err(QBotWsError::AccessTokenError)?;
+    token.insert_str(0, "QQBot ");
+    Ok(token)
 }
 
-impl<S: Unpin + Stream<Item = Result<WsMessage, WsError>>> QBotWebSocketSession<S> {
+impl<S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>>
+    QBotWebSocketSession<S>
+{
     async fn receive_any(&mut self) -> QBotWsResult<(QBotWebSocketAnyPayload, String)> {
-        let msg = self
-            .ws
-            .next()
-            .await
-            .ok_or_else(|| QBotWsError::UnexpectedData("eof".into()))??;
-        let msg = msg
-            .into_text()
-            .map_err(|_| QBotWsError::UnexpectedData("response with non-utf8".into()))?;
-        let payload: QBotWebSocketAnyPayload = match serde_json::from_slice(msg.as_bytes()) {
-            Ok(payload) => {
-                debug!("received ws message: {}", msg);
-                payload
-            }
-            Err(err) => {
-                error!("failed to parse ws message {}: {:?}", msg, err);
-                return Err(err.into());
-            }
-        };
+        let msg = receive_text(&mut self.ws).await?;
+        let payload = deserialize_any_op(&msg)?;
         if let Some(seq) = payload.seq {
             self.last_seq = seq.max(self.last_seq);
         }
@@ -190,10 +301,96 @@ impl<S: Unpin + Stream<Item = Result<WsMessage, WsError>>> QBotWebSocketSession<
     }
 }
 
+fn deserialize_any_op(msg: &str) -> serde_json::Result<QBotWebSocketAnyPayload> {
+    match serde_json::from_str(msg) {
+        Ok(payload) => {
+            debug!("received ws message: {}", msg);
+            Ok(payload)
+        }
+        Err(err) => {
+            error!("failed to parse ws message {}: {:?}", msg, err);
+            Err(err)
+        }
+    }
+}
+
+// Spreads heartbeats of connections sharing the same interval so that they don't line up.
+fn jittered(interval: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return interval;
+    }
+    interval.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+}
+
+impl<S> QBotWebSocketSession<S> {
+    fn publish_diagnostics(&self, diagnostics: &RwLock<WsDiagnostics>, event_received: bool) {
+        let mut diagnostics = diagnostics.write().unwrap();
+        diagnostics.session_id.clone_from(&self.session_id);
+        diagnostics.last_seq = self.last_seq;
+        diagnostics.heartbeat_interval = self.heartbeat_interval;
+        if event_received {
+            diagnostics.last_event_at = Some(Instant::now());
+        }
+    }
+
+    async fn save_debounced(&mut self, session_file: Option<&Path>) {
+        if self
+            .last_saved_at
+            .is_some_and(|last_saved_at| last_saved_at.elapsed() < SESSION_SAVE_INTERVAL)
+        {
+            return;
+        }
+        self.save(session_file).await;
+    }
+
+    async fn save(&mut self, session_file: Option<&Path>) {
+        let Some(session_file) = session_file else {
+            return;
+        };
+        self.last_saved_at = Some(Instant::now());
+        let saved = SavedWsSession {
+            session_id: self.session_id.clone(),
+            last_seq: self.last_seq,
+            bot_user: self.bot_user.clone(),
+        };
+        let res = match serde_json::to_vec(&saved) {
+            Ok(json) => tokio::fs::write(session_file, json).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = res {
+            warn!(?session_file, "failed to save ws session: {e}");
+        }
+    }
+}
+
+fn load_saved_session(session_file: &Path) -> Option<SavedWsSession> {
+    let json = match std::fs::read(session_file) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(?session_file, "failed to read saved ws session: {e}");
+            return None;
+        }
+    };
+    match serde_json::from_slice(&json) {
+        Ok(saved) => Some(saved),
+        Err(e) => {
+            warn!(?session_file, "malformed saved ws session: {e}");
+            None
+        }
+    }
+}
+
 impl<S: Unpin + Sink<WsMessage, Error = WsError>> QBotWebSocketSession<S> {
     async fn send_op<T: Serialize + OpCodePayload>(&mut self, data: &T) -> QBotWsResult<()> {
         send_op(data, &mut self.ws).await
     }
+    async fn send_heartbeat(&mut self) -> QBotWsResult<()> {
+        self.send_op(&HeartbeatPayload).await?;
+        self.next_heartbeat =
+            Instant::now() + jittered(self.heartbeat_interval, self.heartbeat_jitter);
+        Ok(())
+    }
     async fn resume(&mut self, mut ws: S) -> Result<(), (S, QBotWsError)> {
         let payload = ResumePayload {
             token: &self.token,
@@ -209,31 +406,278 @@ impl<S: Unpin + Sink<WsMessage, Error = WsError>> QBotWebSocketSession<S> {
 }
 
 pub trait QBotWsMessageHandler {
+    fn handle_ready(&mut self, _bot_user: &ReadyUser) {}
     fn handle_at_message(&mut self, _payload: AtMessageCreatePayload) {}
+    fn handle_message_delete(&mut self, _payload: MessageDeletePayload) {}
+    // Used by QBotWsDispatchMode::Queued: the returned future is queued and awaited after the ones
+    // returned before it. Other events are still handled right away.
+    fn process_at_message(
+        &mut self,
+        payload: AtMessageCreatePayload,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        self.handle_at_message(payload);
+        std::future::ready(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QBotWsDispatchMode {
+    // Call the handler from the read loop and let it spawn its own work.
+    #[default]
+    Inline,
+    // Hand events to a worker over a bounded queue. Once the queue is full the read loop stops
+    // reading until the worker catches up, but keeps sending heartbeats.
+    Queued {
+        capacity: usize,
+    },
+}
+
+/// Snapshot of the current session published by the run loop, for troubleshooting from chat.
+#[derive(Debug, Clone, Default)]
+pub struct WsDiagnostics {
+    pub session_id: String,
+    pub last_seq: i32,
+    pub heartbeat_interval: Duration,
+    pub last_event_at: Option<Instant>,
+}
+
+pub type SharedWsDiagnostics = Arc<RwLock<WsDiagnostics>>;
+
+/// What a new process needs to resume the session of the previous one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedWsSession {
+    pub session_id: String,
+    pub last_seq: i32,
+    pub bot_user: ReadyUser,
+}
+
+#[derive(Debug, Clone)]
+pub struct QBotWsConfig {
+    pub intents: Intents,
+    pub heartbeat_interval_override: Option<Duration>,
+    pub dispatch_mode: QBotWsDispatchMode,
+    /// Fraction by which each heartbeat interval is randomly stretched or shortened.
+    pub heartbeat_jitter: f64,
+    pub diagnostics: SharedWsDiagnostics,
+    /// Where the session is saved, so that it can be resumed after a restart instead of identifying
+    /// again and missing the events in between. It is saved before every command is dispatched,
+    /// so commands are not run again after a crash, but otherwise at most every 5 seconds, so
+    /// other events received shortly before a crash may be replayed.
+    pub session_file: Option<PathBuf>,
+}
+
+enum WsEvent {
+    Ready(ReadyUser),
+    AtMessage(AtMessageCreatePayload),
+    MessageDelete(MessageDeletePayload),
+}
+
+type QueuedCommand = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Dispatcher<'h, H> {
+    handler: &'h mut H,
+    // Only in QBotWsDispatchMode::Queued.
+    queue: Option<DispatchQueue>,
+}
+
+struct DispatchQueue {
+    tx: mpsc::Sender<QueuedCommand>,
+    permit: Option<mpsc::OwnedPermit<QueuedCommand>>,
+}
+
+impl<'h, H: QBotWsMessageHandler> Dispatcher<'h, H> {
+    fn inline(handler: &'h mut H) -> Self {
+        Self {
+            handler,
+            queue: None,
+        }
+    }
+
+    fn queued(handler: &'h mut H, tx: mpsc::Sender<QueuedCommand>) -> Self {
+        Self {
+            handler,
+            queue: Some(DispatchQueue { tx, permit: None }),
+        }
+    }
+
+    /// Whether the next event can be dispatched without waiting for room in the queue.
+    fn is_ready(&self) -> bool {
+        self.queue
+            .as_ref()
+            .is_none_or(|queue| queue.permit.is_some() || queue.tx.is_closed())
+    }
+
+    /// Waits for room in the queue, so that the read loop can wait for it alongside heartbeats
+    /// instead of blocking in [`Dispatcher::dispatch`].
+    async fn reserve(&mut self) {
+        if let Some(queue) = &mut self.queue {
+            if queue.permit.is_none() {
+                // Fails only if the worker is gone, which `dispatch` reports.
+                queue.permit = queue.tx.clone().reserve_owned().await.ok();
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, event: WsEvent) {
+        let payload = match event {
+            WsEvent::Ready(bot_user) => return self.handler.handle_ready(&bot_user),
+            // Deletes skip the queue, or they would only arrive after the command they are meant
+            // to stop from replying has finished.
+            WsEvent::MessageDelete(payload) => return self.handler.handle_message_delete(payload),
+            WsEvent::AtMessage(payload) => payload,
+        };
+        let Some(queue) = &mut self.queue else {
+            return self.handler.handle_at_message(payload);
+        };
+        let command: QueuedCommand = Box::pin(self.handler.process_at_message(payload));
+        let res = match queue.permit.take() {
+            Some(permit) => {
+                permit.send(command);
+                Ok(())
+            }
+            None => queue.tx.send(command).await.map_err(drop),
+        };
+        if res.is_err() {
+            error!("dispatch worker is gone, dropping event");
+        }
+    }
+}
+
+async fn run_dispatch_worker(mut rx: mpsc::Receiver<QueuedCommand>) {
+    while let Some(command) = rx.recv().await {
+        command.await;
+    }
 }
 
 pub async fn run_loop(
     ws_url: impl Into<String>,
     authorizer: impl QBotAuthorizer + Sync,
-    mut handler: impl QBotWsMessageHandler,
+    handler: impl QBotWsMessageHandler,
     quit_signal: &Notify,
     auth_group: &QBotWebSocketAuthGroup,
+    config: &QBotWsConfig,
 ) -> QBotWsResult<()> {
     let ws_url: String = ws_url.into();
-    let (mut ws, _) = tokio_tungstenite::connect_async(ws_url.as_str()).await?;
-    let mut session = QBotWebSocketHandshaked::handshake(&mut ws, auth_group)
-        .await?
-        .authenticate(&authorizer, ws)
-        .await?;
+    let ws_url = ws_url.as_str();
+    run_loop_with_connector(
+        move || async move {
+            info!("connecting ws, url={}", ws_url);
+            let (ws, _) = tokio_tungstenite::connect_async(ws_url).await?;
+            Ok(ws)
+        },
+        authorizer,
+        handler,
+        quit_signal,
+        auth_group,
+        config,
+    )
+    .await
+}
+
+async fn run_loop_with_connector<S, F, Fut, H>(
+    connect: F,
+    authorizer: impl QBotAuthorizer + Sync,
+    mut handler: H,
+    quit_signal: &Notify,
+    auth_group: &QBotWebSocketAuthGroup,
+    config: &QBotWsConfig,
+) -> QBotWsResult<()>
+where
+    S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = QBotWsResult<S>>,
+    H: QBotWsMessageHandler,
+{
+    match config.dispatch_mode {
+        QBotWsDispatchMode::Inline => {
+            let dispatcher = Dispatcher::inline(&mut handler);
+            run_sessions(
+                connect,
+                authorizer,
+                dispatcher,
+                quit_signal,
+                auth_group,
+                config,
+            )
+            .await
+        }
+        QBotWsDispatchMode::Queued { capacity } => {
+            let (tx, rx) = mpsc::channel(capacity);
+            let dispatcher = Dispatcher::queued(&mut handler, tx);
+            let (res, ()) = tokio::join!(
+                run_sessions(
+                    connect,
+                    authorizer,
+                    dispatcher,
+                    quit_signal,
+                    auth_group,
+                    config
+                ),
+                run_dispatch_worker(rx),
+            );
+            res
+        }
+    }
+}
+
+async fn run_sessions<S, F, Fut, H>(
+    mut connect: F,
+    authorizer: impl QBotAuthorizer + Sync,
+    mut dispatcher: Dispatcher<'_, H>,
+    quit_signal: &Notify,
+    auth_group: &QBotWebSocketAuthGroup,
+    config: &QBotWsConfig,
+) -> QBotWsResult<()>
+where
+    S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = QBotWsResult<S>>,
+    H: QBotWsMessageHandler,
+{
+    let intents = config.intents;
+    let heartbeat_interval_override = config.heartbeat_interval_override;
+    let session_file = config.session_file.as_deref();
+    let mut ws = connect().await?;
+    let mut session = {
+        let handshake = QBotWebSocketHandshaked::handshake(&mut ws, auth_group).await?;
+        match session_file.and_then(load_saved_session) {
+            // Falls back to identifying below if the server rejects it with an invalid session.
+            Some(saved) => {
+                info!("resuming saved ws session {}", saved.session_id);
+                handshake
+                    .resume_saved(&authorizer, ws, saved, heartbeat_interval_override)
+                    .await?
+            }
+            None => {
+                handshake
+                    .authenticate(&authorizer, ws, intents, heartbeat_interval_override)
+                    .await?
+            }
+        }
+    };
     info!(
-        "initial ws connected, url={}, handshake_interval={}",
-        ws_url, session.heartbeat_interval
+        "initial ws connected, handshake_interval={:?}, bot_id={}, bot_username={}",
+        session.heartbeat_interval, session.bot_user.id, session.bot_user.username
     );
+    session.heartbeat_jitter = config.heartbeat_jitter;
+    session.publish_diagnostics(&config.diagnostics, true);
+    session.save(session_file).await;
+    dispatcher
+        .dispatch(WsEvent::Ready(session.bot_user.clone()))
+        .await;
 
-    session.send_op(&HeartbeatPayload).await?;
+    session.send_heartbeat().await?;
     'outer: loop {
-        let result = run_loop_inner(&mut session, &mut handler, quit_signal).await;
+        let result = run_loop_inner(
+            &mut session,
+            &mut dispatcher,
+            quit_signal,
+            &config.diagnostics,
+            session_file,
+        )
+        .await;
         let Err(mut err) = result else { break Ok(()) };
+        session.save(session_file).await;
         'retry: loop {
             if err.is_ignoreable() {
                 info!("ignoring ws error: {:?}", err);
@@ -247,12 +691,18 @@ pub async fn run_loop(
                 sleep(Duration::from_secs(5)).await;
             }
             info!("reconnecting ws");
-            let (mut ws, _) = tokio_tungstenite::connect_async(ws_url.as_str()).await?;
+            METRICS.ws_reconnects.inc();
+            let mut ws = connect().await?;
             let handshake = QBotWebSocketHandshaked::handshake(&mut ws, auth_group).await?;
             if err.is_resumable() {
                 info!("resuming ws session");
                 match session.resume(ws).await {
-                    Ok(()) => continue 'outer,
+                    Ok(()) => {
+                        // Heartbeat right away as the re-identify path does, instead of leaving
+                        // the resumed connection silent for a whole interval.
+                        session.send_heartbeat().await?;
+                        continue 'outer;
+                    }
                     Err((_, resume_err)) => {
                         err = resume_err;
                         error!("failed to resume ws session: {:?}", err);
@@ -261,39 +711,67 @@ pub async fn run_loop(
                 }
             }
             info!("re-identifying ws session");
-            session = handshake.authenticate(&authorizer, ws).await?;
-            session.send_op(&HeartbeatPayload).await?;
+            session = handshake
+                .authenticate(&authorizer, ws, intents, heartbeat_interval_override)
+                .await?;
+            session.heartbeat_jitter = config.heartbeat_jitter;
+            session.publish_diagnostics(&config.diagnostics, true);
+            session.save(session_file).await;
+            dispatcher
+                .dispatch(WsEvent::Ready(session.bot_user.clone()))
+                .await;
+            session.send_heartbeat().await?;
             break 'retry;
         }
     }
 }
 
+fn parse_event<T: DeserializeOwned>(event_type: &str, data: &str) -> Option<T> {
+    match serde_json::from_str(data) {
+        Ok(payload) => Some(payload),
+        Err(err) => {
+            error!("malformed {} payload {}: {:?}", event_type, data, err);
+            METRICS.ws_malformed_events.inc();
+            None
+        }
+    }
+}
+
 async fn run_loop_inner<
     S: Unpin + Stream<Item = Result<WsMessage, WsError>> + Sink<WsMessage, Error = WsError>,
 >(
     session: &mut QBotWebSocketSession<S>,
-    handler: &mut impl QBotWsMessageHandler,
+    dispatcher: &mut Dispatcher<'_, impl QBotWsMessageHandler>,
     quit_signal: &Notify,
+    diagnostics: &RwLock<WsDiagnostics>,
+    session_file: Option<&Path>,
 ) -> QBotWsResult<()> {
     'run_loop: loop {
         let (metadata, data) = tokio::select! {
             biased;
             _ = quit_signal.notified() =>{
                 info!("closing ws session {}", session.session_id);
+                session.save(session_file).await;
                 session.ws.close().await?;
                 break 'run_loop Ok(())
             },
-            _ = sleep(Duration::from_secs(session.heartbeat_interval)) => {
-                session.send_op(&HeartbeatPayload).await?;
+            _ = sleep_until(session.next_heartbeat) => {
+                session.send_heartbeat().await?;
                 continue 'run_loop;
             },
-            msg = session.receive_any() => msg,
+            // Backpressure from a queued dispatcher stops reading, not heartbeats.
+            _ = dispatcher.reserve(), if !dispatcher.is_ready() => continue 'run_loop,
+            msg = session.receive_any(), if dispatcher.is_ready() => msg,
         }?;
+        session.publish_diagnostics(diagnostics, true);
         let event_type = match metadata.opcode {
-            OpCode::OP_DISPATCH => metadata.event_type.unwrap_or_default(),
+            OpCode::OP_DISPATCH => {
+                METRICS.ws_events_received.inc();
+                metadata.event_type.unwrap_or_default()
+            }
             OpCode::OP_HEARTBEAT => {
                 debug!("received heartbeat");
-                session.send_op(&HeartbeatPayload).await?;
+                session.send_heartbeat().await?;
                 continue 'run_loop;
             }
             OpCode::OP_RECONNECT => break Err(QBotWsError::ReturnCodeError(7)),
@@ -307,35 +785,972 @@ async fn run_loop_inner<
                 continue 'run_loop;
             }
         };
-        match &*event_type {
-            "RESUMED" => {
-                info!("resumed ws session");
-            }
-            "AT_MESSAGE_CREATE" => {
-                let msg: QBotWebSocketPayload<AtMessageCreatePayload> =
-                    serde_json::from_slice(data.as_bytes())?;
-                handler.handle_at_message(msg.data);
-            }
-            "DIRECT_MESSAGE_CREATE" => {
-                let _msg: QBotWebSocketPayload<DirectMessageCreatePayload> =
-                    serde_json::from_slice(data.as_bytes())?;
-                // handler.handle_at_message(AtMessageCreatePayload {
-                //     author: msg.data.author,
-                //     channel_id: msg.data.channel_id,
-                //     content: msg.data.content,
-                //     guild_id: msg.data.guild_id,
-                //     id: msg.data.id,
-                //     member: msg.data.member,
-                //     timestamp: msg.data.timestamp,
-                //     seq: Default::default(),
-                // })
-            }
-            "PUBLIC_MESSAGE_DELETE" => {
-                info!("received ws event {}", event_type);
-            }
-            _ => {
-                warn!("unhandled ws event {}", event_type);
+        // Events are replayed from the saved seq on resume, and replaying a command would run its
+        // side effects again.
+        if event_type == "AT_MESSAGE_CREATE" {
+            session.save(session_file).await;
+        } else {
+            session.save_debounced(session_file).await;
+        }
+        handle_dispatch_event(&event_type, &data, dispatcher).await;
+    }
+}
+
+async fn handle_dispatch_event(
+    event_type: &str,
+    data: &str,
+    dispatcher: &mut Dispatcher<'_, impl QBotWsMessageHandler>,
+) {
+    match event_handling(event_type) {
+        None => {
+            warn!("unhandled ws event {}", event_type);
+            return;
+        }
+        Some(EventHandling::LogOnly) => {
+            info!("received ws event {}", event_type);
+            return;
+        }
+        Some(EventHandling::Handled | EventHandling::Dropped) => {}
+    }
+    match event_type {
+        "AT_MESSAGE_CREATE" => {
+            let Some(msg) =
+                parse_event::<QBotWebSocketPayload<AtMessageCreatePayload>>(event_type, data)
+            else {
+                return;
+            };
+            dispatcher.dispatch(WsEvent::AtMessage(msg.data)).await;
+        }
+        "DIRECT_MESSAGE_CREATE" => {
+            let Some(_msg) =
+                parse_event::<QBotWebSocketPayload<DirectMessageCreatePayload>>(event_type, data)
+            else {
+                return;
+            };
+            // handler.handle_at_message(AtMessageCreatePayload {
+            //     author: msg.data.author,
+            //     channel_id: msg.data.channel_id,
+            //     content: msg.data.content,
+            //     guild_id: msg.data.guild_id,
+            //     id: msg.data.id,
+            //     member: msg.data.member,
+            //     timestamp: msg.data.timestamp,
+            //     seq: Default::default(),
+            // })
+        }
+        "PUBLIC_MESSAGE_DELETE" => {
+            let Some(msg) =
+                parse_event::<QBotWebSocketPayload<MessageDeletePayload>>(event_type, data)
+            else {
+                return;
+            };
+            dispatcher.dispatch(WsEvent::MessageDelete(msg.data)).await;
+        }
+        _ => {}
+    }
+}
+
+/// Runs a raw gateway message through parsing and dispatch, with a handler that ignores every
+/// event. Only meant for the fuzz targets under `fuzz/`, which enable the `testing` feature.
+#[cfg(any(test, fuzzing, feature = "testing"))]
+#[doc(hidden)]
+pub async fn fuzz_gateway_message(msg: &str) {
+    struct NoopHandler;
+    impl QBotWsMessageHandler for NoopHandler {}
+
+    let Ok(payload) = deserialize_any_op(msg) else {
+        return;
+    };
+    if payload.opcode == OpCode::OP_DISPATCH {
+        let event_type = payload.event_type.unwrap_or_default();
+        let mut handler = NoopHandler;
+        handle_dispatch_event(&event_type, msg, &mut Dispatcher::inline(&mut handler)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context, Poll};
+
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+    use serde_json::{json, Value};
+    use tokio::sync::Semaphore;
+    use tokio_tungstenite::tungstenite::error::ProtocolError;
+    use tokio_tungstenite::tungstenite::protocol::frame::{coding::CloseCode, CloseFrame};
+
+    use crate::qbot::authorizer::MockAuthorizer;
+    use crate::qbot::{MessageId, QBotApiError};
+    use crate::test_util::capture_logs;
+    use tracing::Level;
+
+    use super::*;
+
+    struct MockWs {
+        incoming: UnboundedReceiver<Result<WsMessage, WsError>>,
+        outgoing: UnboundedSender<WsMessage>,
+    }
+
+    impl Stream for MockWs {
+        type Item = Result<WsMessage, WsError>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.incoming.poll_next_unpin(cx)
+        }
+    }
+
+    impl Sink<WsMessage> for MockWs {
+        type Error = WsError;
+
+        fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+        fn start_send(self: Pin<&mut Self>, item: WsMessage) -> Result<(), WsError> {
+            self.outgoing
+                .unbounded_send(item)
+                .map_err(|_| WsError::ConnectionClosed)
+        }
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Returns the client end of a mock connection, a sender to push server frames and a
+    /// receiver of frames sent by the client.
+    fn mock_ws() -> (
+        MockWs,
+        UnboundedSender<Result<WsMessage, WsError>>,
+        UnboundedReceiver<WsMessage>,
+    ) {
+        let (server_tx, incoming) = unbounded();
+        let (outgoing, client_rx) = unbounded();
+        (MockWs { incoming, outgoing }, server_tx, client_rx)
+    }
+
+    fn text(value: Value) -> WsMessage {
+        WsMessage::Text(value.to_string())
+    }
+
+    fn hello() -> WsMessage {
+        text(json!({ "op": 10, "d": { "heartbeat_interval": 45000 } }))
+    }
+
+    fn ready(seq: i32) -> WsMessage {
+        text(json!({
+            "op": 0,
+            "s": seq,
+            "t": "READY",
+            "d": {
+                "version": 1,
+                "session_id": "sessionId",
+                "user": { "id": "botId", "username": "botName", "bot": true },
+                "shard": [0, 1]
+            }
+        }))
+    }
+
+    struct NoopHandler;
+    impl QBotWsMessageHandler for NoopHandler {}
+
+    fn session(ws: MockWs) -> QBotWebSocketSession<MockWs> {
+        QBotWebSocketSession {
+            ws,
+            session_id: "sessionId".into(),
+            heartbeat_interval: Duration::from_secs(30),
+            heartbeat_jitter: 0.0,
+            next_heartbeat: Instant::now(),
+            token: "QQBot accessToken".into(),
+            last_seq: 1,
+            bot_user: Default::default(),
+            last_saved_at: None,
+        }
+    }
+
+    fn connector<const N: usize>(
+        connections: [MockWs; N],
+    ) -> impl FnMut() -> std::future::Ready<QBotWsResult<MockWs>> {
+        let mut connections = VecDeque::from(connections);
+        move || {
+            std::future::ready(
+                connections
+                    .pop_front()
+                    .ok_or_else(|| QBotWsError::UnexpectedData("no more ws".into())),
+            )
+        }
+    }
+
+    fn config(dispatch_mode: QBotWsDispatchMode) -> QBotWsConfig {
+        QBotWsConfig {
+            intents: Intents::PUBLIC_GUILD_MESSAGES,
+            heartbeat_interval_override: None,
+            dispatch_mode,
+            heartbeat_jitter: 0.0,
+            diagnostics: Default::default(),
+            session_file: None,
+        }
+    }
+
+    fn at_message(id: &str) -> WsMessage {
+        text(json!({
+            "op": 0,
+            "s": 2,
+            "t": "AT_MESSAGE_CREATE",
+            "d": {
+                "author": { "avatar": "", "id": "authorId", "username": "author" },
+                "channel_id": "channelId",
+                "content": "<@!botId> 帮助",
+                "guild_id": "guildId",
+                "id": id,
+                "member": { "joined_at": "2024-01-01T00:00:00+08:00", "roles": [] },
+                "timestamp": "2024-04-14T00:00:00+08:00",
+                "seq": 2
+            }
+        }))
+    }
+
+    fn message_delete(id: &str) -> WsMessage {
+        text(json!({
+            "op": 0,
+            "s": 3,
+            "t": "PUBLIC_MESSAGE_DELETE",
+            "d": { "message": { "id": id, "channel_id": "channelId" } }
+        }))
+    }
+
+    fn parse_sent(msg: WsMessage) -> Value {
+        serde_json::from_str(&msg.into_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_authenticate_captures_bot_user() {
+        let (mut ws, server_tx, mut client_rx) = mock_ws();
+        server_tx.unbounded_send(Ok(hello())).unwrap();
+        server_tx.unbounded_send(Ok(ready(1))).unwrap();
+
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let session = QBotWebSocketHandshaked::handshake(&mut ws, &auth_group)
+            .await
+            .unwrap()
+            .authenticate(
+                MockAuthorizer("accessToken".into()),
+                ws,
+                Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(session.session_id, "sessionId");
+        assert_eq!(session.last_seq, 1);
+        assert_eq!(
+            session.bot_user,
+            ReadyUser {
+                id: "botId".into(),
+                username: "botName".into(),
+                bot: true,
+            }
+        );
+        let identify: Value =
+            serde_json::from_str(&client_rx.next().await.unwrap().into_text().unwrap()).unwrap();
+        assert_eq!(identify["op"], 2);
+        assert_eq!(identify["d"]["token"], "QQBot accessToken");
+        assert_eq!(identify["d"]["intents"], (1 << 30) | (1 << 12));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warn_on_missing_intents() {
+        let logs = capture_logs();
+
+        let (mut ws, server_tx, _client_rx) = mock_ws();
+        server_tx.unbounded_send(Ok(hello())).unwrap();
+        let mut ready = parse_sent(ready(1));
+        ready["d"]["intents"] = json!(u64::from(Intents::PUBLIC_GUILD_MESSAGES));
+        server_tx.unbounded_send(Ok(text(ready))).unwrap();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        QBotWebSocketHandshaked::handshake(&mut ws, &auth_group)
+            .await
+            .unwrap()
+            .authenticate(
+                MockAuthorizer("accessToken".into()),
+                ws,
+                Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let warning = logs.find(Level::WARN, "some requested intents were not granted");
+        assert!(
+            warning.contains(r#"missing={"DIRECT_MESSAGE"}"#),
+            "{warning}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_after_resume() {
+        let (ws1, server_tx1, _client_rx1) = mock_ws();
+        server_tx1.unbounded_send(Ok(hello())).unwrap();
+        server_tx1.unbounded_send(Ok(ready(1))).unwrap();
+        server_tx1
+            .unbounded_send(Err(WsError::Protocol(
+                ProtocolError::ResetWithoutClosingHandshake,
+            )))
+            .unwrap();
+        let (ws2, server_tx2, mut client_rx2) = mock_ws();
+        server_tx2.unbounded_send(Ok(hello())).unwrap();
+
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = config(QBotWsDispatchMode::Inline);
+        let run = run_loop_with_connector(
+            connector([ws1, ws2]),
+            MockAuthorizer("accessToken".into()),
+            NoopHandler,
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            let resume = parse_sent(client_rx2.next().await.unwrap());
+            let resumed_at = Instant::now();
+            assert_eq!(resume["op"], 6);
+            assert_eq!(resume["d"]["session_id"], "sessionId");
+            assert_eq!(resume["d"]["seq"], 1);
+            let heartbeat = parse_sent(client_rx2.next().await.unwrap());
+            assert_eq!(heartbeat["op"], 1);
+            assert_eq!(Instant::now(), resumed_at);
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_saved_session_after_restart() {
+        struct RecordingHandler(UnboundedSender<String>);
+        impl QBotWsMessageHandler for RecordingHandler {
+            fn handle_ready(&mut self, bot_user: &ReadyUser) {
+                self.0.unbounded_send(bot_user.id.clone()).unwrap();
+            }
+            fn handle_at_message(&mut self, payload: AtMessageCreatePayload) {
+                self.0.unbounded_send(payload.id.to_string()).unwrap();
+            }
+        }
+
+        let session_file =
+            std::env::temp_dir().join(format!("qbot-ws-session-{}", std::process::id()));
+        let saved = SavedWsSession {
+            session_id: "savedSessionId".into(),
+            last_seq: 5,
+            bot_user: ReadyUser {
+                id: "botId".into(),
+                username: "botName".into(),
+                bot: true,
+            },
+        };
+        std::fs::write(&session_file, serde_json::to_vec(&saved).unwrap()).unwrap();
+
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        server_tx.unbounded_send(Ok(hello())).unwrap();
+        let (handler_tx, mut handler_rx) = unbounded();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = QBotWsConfig {
+            session_file: Some(session_file.clone()),
+            ..config(QBotWsDispatchMode::Inline)
+        };
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            RecordingHandler(handler_tx),
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            let resume = parse_sent(client_rx.next().await.unwrap());
+            assert_eq!(resume["op"], 6);
+            assert_eq!(resume["d"]["token"], "QQBot accessToken");
+            assert_eq!(resume["d"]["session_id"], "savedSessionId");
+            assert_eq!(resume["d"]["seq"], 5);
+            assert_eq!(handler_rx.next().await.unwrap(), "botId");
+
+            server_tx
+                .unbounded_send(Ok(text(
+                    json!({ "op": 0, "s": 6, "t": "RESUMED", "d": "" }),
+                )))
+                .unwrap();
+            let mut message = parse_sent(at_message("messageId"));
+            message["s"] = json!(7);
+            server_tx.unbounded_send(Ok(text(message))).unwrap();
+            assert_eq!(handler_rx.next().await.unwrap(), "messageId");
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+
+        let saved: SavedWsSession =
+            serde_json::from_slice(&std::fs::read(&session_file).unwrap()).unwrap();
+        std::fs::remove_file(&session_file).unwrap();
+        assert_eq!(saved.session_id, "savedSessionId");
+        assert_eq!(saved.last_seq, 7);
+        assert_eq!(saved.bot_user.id, "botId");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_saved_before_command() {
+        struct SessionReadingHandler(PathBuf, UnboundedSender<i32>);
+        impl QBotWsMessageHandler for SessionReadingHandler {
+            fn handle_at_message(&mut self, _payload: AtMessageCreatePayload) {
+                let saved: SavedWsSession =
+                    serde_json::from_slice(&std::fs::read(&self.0).unwrap()).unwrap();
+                self.1.unbounded_send(saved.last_seq).unwrap();
+            }
+        }
+
+        let session_file =
+            std::env::temp_dir().join(format!("qbot-ws-session-cmd-{}", std::process::id()));
+        let _ = std::fs::remove_file(&session_file);
+        let (ws, server_tx, _client_rx) = mock_ws();
+        let mut message = parse_sent(at_message("messageId"));
+        message["s"] = json!(7);
+        for msg in [hello(), ready(1), text(message)] {
+            server_tx.unbounded_send(Ok(msg)).unwrap();
+        }
+        let (handler_tx, mut handler_rx) = unbounded();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = QBotWsConfig {
+            session_file: Some(session_file.clone()),
+            ..config(QBotWsDispatchMode::Inline)
+        };
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            SessionReadingHandler(session_file.clone(), handler_tx),
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            // Within the debounce interval of the save after identifying.
+            assert_eq!(handler_rx.next().await.unwrap(), 7);
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        std::fs::remove_file(&session_file).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_interval_override() {
+        async fn authenticate(override_interval: Option<Duration>) -> Duration {
+            let (mut ws, server_tx, _client_rx) = mock_ws();
+            server_tx.unbounded_send(Ok(hello())).unwrap();
+            server_tx.unbounded_send(Ok(ready(1))).unwrap();
+            let auth_group = QBotWebSocketAuthGroup::new();
+            let session = QBotWebSocketHandshaked::handshake(&mut ws, &auth_group)
+                .await
+                .unwrap()
+                .authenticate(
+                    MockAuthorizer("accessToken".into()),
+                    ws,
+                    Intents::PUBLIC_GUILD_MESSAGES,
+                    override_interval,
+                )
+                .await
+                .unwrap();
+            session.heartbeat_interval
+        }
+
+        assert_eq!(authenticate(None).await, Duration::from_millis(45000));
+        assert_eq!(
+            authenticate(Some(Duration::from_secs(30))).await,
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_jittered_heartbeat_within_bounds() {
+        let interval = Duration::from_secs(30);
+        assert_eq!(jittered(interval, 0.0), interval);
+        let samples = (0..1000)
+            .map(|_| jittered(interval, 0.1))
+            .collect::<Vec<_>>();
+        assert!(samples
+            .iter()
+            .all(|d| (Duration::from_secs(27)..=Duration::from_secs(33)).contains(d)));
+        assert!(samples.iter().any(|d| *d != samples[0]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_handshakes_spaced_apart() {
+        let auth_group = QBotWebSocketAuthGroup::with_min_interval(Duration::from_secs(10));
+        let (mut ws1, server_tx1, _client_rx1) = mock_ws();
+        server_tx1.unbounded_send(Ok(hello())).unwrap();
+        let (mut ws2, server_tx2, _client_rx2) = mock_ws();
+        server_tx2.unbounded_send(Ok(hello())).unwrap();
+
+        let first = QBotWebSocketHandshaked::handshake(&mut ws1, &auth_group)
+            .await
+            .unwrap();
+        sleep(Duration::from_secs(3)).await;
+        drop(first);
+        let released_at = Instant::now();
+
+        let _second = QBotWebSocketHandshaked::handshake(&mut ws2, &auth_group)
+            .await
+            .unwrap();
+        assert_eq!(Instant::now() - released_at, Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_respond_to_ping() {
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        server_tx
+            .unbounded_send(Ok(WsMessage::Ping(b"ping".to_vec())))
+            .unwrap();
+        server_tx
+            .unbounded_send(Ok(WsMessage::Pong(b"pong".to_vec())))
+            .unwrap();
+        server_tx
+            .unbounded_send(Ok(text(json!({ "op": 11 }))))
+            .unwrap();
+        let mut session = session(ws);
+
+        let (metadata, _) = session.receive_any().await.unwrap();
+        assert_eq!(metadata.opcode, OpCode::OP_HEARTBEAT_ACK);
+        assert_eq!(
+            client_rx.next().await.unwrap(),
+            WsMessage::Pong(b"ping".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_frame_is_recoverable() {
+        let (ws, server_tx, _client_rx) = mock_ws();
+        server_tx
+            .unbounded_send(Ok(WsMessage::Close(Some(CloseFrame {
+                code: CloseCode::Library(4009),
+                reason: "session timed out".into(),
+            }))))
+            .unwrap();
+        server_tx
+            .unbounded_send(Ok(WsMessage::Close(None)))
+            .unwrap();
+        let mut session = session(ws);
+
+        let err = session.receive_any().await.unwrap_err();
+        assert!(matches!(err, QBotWsError::Closed(Some(4009))));
+        assert!(err.is_recoverable());
+        assert!(err.is_resumable());
+        assert!(!err.is_ignoreable());
+
+        let err = session.receive_any().await.unwrap_err();
+        assert!(matches!(err, QBotWsError::Closed(None)));
+        assert!(err.is_recoverable());
+        assert!(!err.is_resumable());
+    }
+
+    #[test]
+    fn test_access_token_error_is_recoverable() {
+        fn access_token_error(status_code: u16) -> QBotWsError {
+            QBotWsError::AccessTokenError(QBotApiError::ApiError {
+                status_code,
+                code: 0,
+                message: String::new(),
+                trace_id: String::new(),
+            })
+        }
+
+        assert!(access_token_error(429).is_recoverable());
+        assert!(access_token_error(502).is_recoverable());
+        assert!(!access_token_error(401).is_recoverable());
+        assert!(!access_token_error(403).is_recoverable());
+    }
+
+    /// Processes each at message only once a permit is added to `release`.
+    #[derive(Clone)]
+    struct StallingHandler {
+        started: Arc<AtomicUsize>,
+        processed: Arc<AtomicUsize>,
+        release: Arc<Semaphore>,
+    }
+
+    impl Default for StallingHandler {
+        fn default() -> Self {
+            Self {
+                started: Default::default(),
+                processed: Default::default(),
+                release: Arc::new(Semaphore::new(0)),
+            }
+        }
+    }
+
+    impl QBotWsMessageHandler for StallingHandler {
+        fn process_at_message(
+            &mut self,
+            _payload: AtMessageCreatePayload,
+        ) -> impl Future<Output = ()> + Send + 'static {
+            let this = self.clone();
+            async move {
+                this.started.fetch_add(1, Ordering::SeqCst);
+                this.release.acquire().await.unwrap().forget();
+                this.processed.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queued_dispatch_backpressure() {
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        for msg in [
+            hello(),
+            ready(1),
+            at_message("message1"),
+            at_message("message2"),
+            at_message("message3"),
+            text(json!({ "op": 1 })),
+        ] {
+            server_tx.unbounded_send(Ok(msg)).unwrap();
+        }
+        let handler = StallingHandler::default();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = config(QBotWsDispatchMode::Queued { capacity: 1 });
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            handler.clone(),
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 2);
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+            sleep(Duration::from_secs(1)).await;
+            // message1 is being processed, message2 fills the queue and message3 waits for room in
+            // it, so the server heartbeat request behind it has not been read.
+            assert_eq!(handler.started.load(Ordering::SeqCst), 1);
+            assert_eq!(handler.processed.load(Ordering::SeqCst), 0);
+            assert!(client_rx.try_next().is_err());
+
+            handler.release.add_permits(3);
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        assert_eq!(handler.processed.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_while_dispatch_queue_full() {
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        for msg in [
+            hello(),
+            ready(1),
+            at_message("message1"),
+            at_message("message2"),
+            at_message("message3"),
+        ] {
+            server_tx.unbounded_send(Ok(msg)).unwrap();
+        }
+        let handler = StallingHandler::default();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = config(QBotWsDispatchMode::Queued { capacity: 1 });
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            handler.clone(),
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 2);
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+            // The worker stays stuck on message1 for two heartbeat intervals.
+            for _ in 0..2 {
+                let started = Instant::now();
+                let heartbeat = tokio::time::timeout(Duration::from_secs(60), client_rx.next())
+                    .await
+                    .expect("no heartbeat while the dispatch queue is full");
+                assert_eq!(parse_sent(heartbeat.unwrap())["op"], 1);
+                assert_eq!(started.elapsed(), Duration::from_secs(45));
+            }
+            assert_eq!(handler.started.load(Ordering::SeqCst), 1);
+            assert_eq!(handler.processed.load(Ordering::SeqCst), 0);
+
+            handler.release.add_permits(3);
+            while handler.processed.load(Ordering::SeqCst) < 3 {
+                tokio::task::yield_now().await;
+            }
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queued_delete_while_processing() {
+        /// Replies to each at message after 10 seconds unless it has been deleted by then.
+        #[derive(Clone, Default)]
+        struct SlowHandler {
+            deleted: Arc<Mutex<Vec<MessageId>>>,
+            replied: Arc<Mutex<Vec<MessageId>>>,
+        }
+        impl QBotWsMessageHandler for SlowHandler {
+            fn handle_message_delete(&mut self, payload: MessageDeletePayload) {
+                self.deleted.lock().unwrap().push(payload.message.id);
+            }
+            fn process_at_message(
+                &mut self,
+                payload: AtMessageCreatePayload,
+            ) -> impl Future<Output = ()> + Send + 'static {
+                let this = self.clone();
+                async move {
+                    sleep(Duration::from_secs(10)).await;
+                    if !this.deleted.lock().unwrap().contains(&payload.id) {
+                        this.replied.lock().unwrap().push(payload.id);
+                    }
+                }
+            }
+        }
+
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        for msg in [
+            hello(),
+            ready(1),
+            at_message("message1"),
+            at_message("message2"),
+        ] {
+            server_tx.unbounded_send(Ok(msg)).unwrap();
+        }
+        let handler = SlowHandler::default();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = config(QBotWsDispatchMode::Queued { capacity: 2 });
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            handler.clone(),
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 2);
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+            sleep(Duration::from_secs(1)).await;
+            // message1 is still being processed with message2 queued behind it.
+            server_tx
+                .unbounded_send(Ok(message_delete("message1")))
+                .unwrap();
+            sleep(Duration::from_secs(30)).await;
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        assert_eq!(*handler.deleted.lock().unwrap(), ["message1"]);
+        assert_eq!(*handler.replied.lock().unwrap(), ["message2"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_once_per_interval() {
+        let (ws, server_tx, mut client_rx) = mock_ws();
+        server_tx.unbounded_send(Ok(hello())).unwrap();
+        server_tx.unbounded_send(Ok(ready(1))).unwrap();
+        let quit_signal = Notify::new();
+        let auth_group = QBotWebSocketAuthGroup::new();
+        let config = QBotWsConfig {
+            heartbeat_interval_override: Some(Duration::from_secs(30)),
+            ..config(QBotWsDispatchMode::Inline)
+        };
+        let run = run_loop_with_connector(
+            connector([ws]),
+            MockAuthorizer("accessToken".into()),
+            NoopHandler,
+            &quit_signal,
+            &auth_group,
+            &config,
+        );
+        let script = async {
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 2);
+            assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+            let started_at = Instant::now();
+
+            // Traffic from the server must not push the next heartbeat back.
+            sleep(Duration::from_secs(10)).await;
+            server_tx
+                .unbounded_send(Ok(text(json!({ "op": 11 }))))
+                .unwrap();
+
+            for i in 1..=3 {
+                assert_eq!(parse_sent(client_rx.next().await.unwrap())["op"], 1);
+                assert_eq!(started_at.elapsed(), Duration::from_secs(30 * i));
+            }
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        assert!(matches!(client_rx.try_next(), Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_event_is_skipped() {
+        struct RecordingHandler(UnboundedSender<String>);
+        impl QBotWsMessageHandler for RecordingHandler {
+            fn handle_at_message(&mut self, payload: AtMessageCreatePayload) {
+                self.0.unbounded_send(payload.id.to_string()).unwrap();
+            }
+        }
+
+        let logs = capture_logs();
+        let (ws, server_tx, _client_rx) = mock_ws();
+        server_tx
+            .unbounded_send(Ok(text(json!({
+                "op": 0,
+                "s": 2,
+                "t": "AT_MESSAGE_CREATE",
+                "d": { "id": "message1", "content": "missing author" }
+            }))))
+            .unwrap();
+        server_tx
+            .unbounded_send(Ok(at_message("message2")))
+            .unwrap();
+        let (handled_tx, mut handled_rx) = unbounded();
+        let mut handler = RecordingHandler(handled_tx);
+        let mut session = session(ws);
+        let quit_signal = Notify::new();
+        let mut dispatcher = Dispatcher::inline(&mut handler);
+        let malformed_before = METRICS.ws_malformed_events.get();
+
+        let diagnostics = RwLock::default();
+        let run = run_loop_inner(
+            &mut session,
+            &mut dispatcher,
+            &quit_signal,
+            &diagnostics,
+            None,
+        );
+        let script = async {
+            assert_eq!(handled_rx.next().await.unwrap(), "message2");
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        let error = logs.find(Level::ERROR, "malformed AT_MESSAGE_CREATE payload");
+        assert!(error.contains("missing author"), "{error}");
+        // Other tests feed malformed events in parallel, so the global counter may have moved on.
+        assert!(METRICS.ws_malformed_events.get() > malformed_before);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_opcode_is_logged_and_skipped() {
+        struct RecordingHandler(UnboundedSender<String>);
+        impl QBotWsMessageHandler for RecordingHandler {
+            fn handle_at_message(&mut self, payload: AtMessageCreatePayload) {
+                self.0.unbounded_send(payload.id.to_string()).unwrap();
+            }
+        }
+
+        let logs = capture_logs();
+        let (ws, server_tx, _client_rx) = mock_ws();
+        server_tx
+            .unbounded_send(Ok(text(json!({ "op": 255, "d": { "unexpected": true } }))))
+            .unwrap();
+        server_tx
+            .unbounded_send(Ok(at_message("message2")))
+            .unwrap();
+        let (handled_tx, mut handled_rx) = unbounded();
+        let mut handler = RecordingHandler(handled_tx);
+        let mut session = session(ws);
+        let quit_signal = Notify::new();
+        let mut dispatcher = Dispatcher::inline(&mut handler);
+
+        let diagnostics = RwLock::default();
+        let run = run_loop_inner(
+            &mut session,
+            &mut dispatcher,
+            &quit_signal,
+            &diagnostics,
+            None,
+        );
+        let script = async {
+            assert_eq!(handled_rx.next().await.unwrap(), "message2");
+            quit_signal.notify_waiters();
+        };
+        let (res, ()) = tokio::join!(run, script);
+        res.unwrap();
+        let warning = logs.find(Level::WARN, "unknown opcode");
+        assert!(warning.contains("255"), "{warning}");
+        assert!(warning.contains("unexpected"), "{warning}");
+    }
+
+    fn fuzz_seeds() -> Vec<String> {
+        vec![
+            hello().into_text().unwrap().to_string(),
+            ready(1).into_text().unwrap().to_string(),
+            at_message("message1").into_text().unwrap().to_string(),
+            message_delete("message1").into_text().unwrap().to_string(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_malformed_gateway_messages() {
+        let truncated = at_message("message1").into_text().unwrap().to_string();
+        let truncated = &truncated[..truncated.len() / 2];
+        let cases = [
+            (truncated, false),
+            (
+                r#"{"op":0,"s":99999999999999999999,"t":"READY","d":{}}"#,
+                false,
+            ),
+            (r#"{"op":0,"s":-1,"t":"AT_MESSAGE_CREATE","d":{}}"#, true),
+            (r#"{"s":1,"t":"READY","d":{}}"#, false),
+            (r#"{"op":0,"t":"AT_MESSAGE_CREATE"}"#, true),
+            (r#"{"op":0,"s":1,"t":"UNKNOWN_EVENT","d":null}"#, true),
+            (r#"{"op":255}"#, true),
+            ("", false),
+        ];
+        for (msg, parses) in cases {
+            assert_eq!(deserialize_any_op(msg).is_ok(), parses, "{msg}");
+            fuzz_gateway_message(msg).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fuzz_gateway_messages() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(1651);
+        let seeds = fuzz_seeds();
+        for _ in 0..2000 {
+            let mut bytes = seeds[rng.gen_range(0..seeds.len())].clone().into_bytes();
+            for _ in 0..rng.gen_range(1..4) {
+                let pos = rng.gen_range(0..bytes.len());
+                match rng.gen_range(0..3) {
+                    0 => bytes.truncate(pos),
+                    1 => bytes[pos] = rng.gen_range(0x20..0x7f),
+                    _ => {
+                        bytes.remove(pos);
+                    }
+                }
+                if bytes.is_empty() {
+                    break;
+                }
             }
+            fuzz_gateway_message(&String::from_utf8_lossy(&bytes)).await;
         }
     }
 }
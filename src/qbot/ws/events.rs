@@ -0,0 +1,53 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventHandling {
+    // Passed on to the message handler.
+    Handled,
+    // Parsed, then dropped.
+    Dropped,
+    // Only logged.
+    LogOnly,
+}
+
+const KNOWN_EVENT_TYPES: [(&str, EventHandling); 5] = [
+    ("READY", EventHandling::Handled),
+    ("RESUMED", EventHandling::LogOnly),
+    ("AT_MESSAGE_CREATE", EventHandling::Handled),
+    ("DIRECT_MESSAGE_CREATE", EventHandling::Dropped),
+    ("PUBLIC_MESSAGE_DELETE", EventHandling::Handled),
+];
+
+/// Dispatch event types the gateway client knows about, and what it does with each of them.
+pub fn known_event_types() -> &'static [(&'static str, EventHandling)] {
+    &KNOWN_EVENT_TYPES
+}
+
+pub(super) fn event_handling(event_type: &str) -> Option<EventHandling> {
+    KNOWN_EVENT_TYPES
+        .iter()
+        .find(|(known, _)| *known == event_type)
+        .map(|(_, handling)| *handling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_event_types() {
+        assert_eq!(
+            known_event_types(),
+            [
+                ("READY", EventHandling::Handled),
+                ("RESUMED", EventHandling::LogOnly),
+                ("AT_MESSAGE_CREATE", EventHandling::Handled),
+                ("DIRECT_MESSAGE_CREATE", EventHandling::Dropped),
+                ("PUBLIC_MESSAGE_DELETE", EventHandling::Handled),
+            ]
+        );
+        assert_eq!(
+            event_handling("AT_MESSAGE_CREATE"),
+            Some(EventHandling::Handled)
+        );
+        assert_eq!(event_handling("GUILD_CREATE"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::Debug;
+use std::ops::{BitOr, BitOrAssign};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Intents(u64);
+
+impl Intents {
+    pub const GUILDS: Intents = Intents(1 << 0);
+    pub const GUILD_MEMBERS: Intents = Intents(1 << 1);
+    pub const GUILD_MESSAGES: Intents = Intents(1 << 9);
+    pub const GUILD_MESSAGE_REACTIONS: Intents = Intents(1 << 10);
+    pub const DIRECT_MESSAGE: Intents = Intents(1 << 12);
+    pub const GROUP_AND_C2C_EVENT: Intents = Intents(1 << 25);
+    pub const INTERACTION: Intents = Intents(1 << 26);
+    pub const MESSAGE_AUDIT: Intents = Intents(1 << 27);
+    pub const FORUMS_EVENT: Intents = Intents(1 << 28);
+    pub const AUDIO_ACTION: Intents = Intents(1 << 29);
+    pub const PUBLIC_GUILD_MESSAGES: Intents = Intents(1 << 30);
+
+    const NAMES: [(Intents, &'static str); 11] = [
+        (Self::GUILDS, "GUILDS"),
+        (Self::GUILD_MEMBERS, "GUILD_MEMBERS"),
+        (Self::GUILD_MESSAGES, "GUILD_MESSAGES"),
+        (Self::GUILD_MESSAGE_REACTIONS, "GUILD_MESSAGE_REACTIONS"),
+        (Self::DIRECT_MESSAGE, "DIRECT_MESSAGE"),
+        (Self::GROUP_AND_C2C_EVENT, "GROUP_AND_C2C_EVENT"),
+        (Self::INTERACTION, "INTERACTION"),
+        (Self::MESSAGE_AUDIT, "MESSAGE_AUDIT"),
+        (Self::FORUMS_EVENT, "FORUMS_EVENT"),
+        (Self::AUDIO_ACTION, "AUDIO_ACTION"),
+        (Self::PUBLIC_GUILD_MESSAGES, "PUBLIC_GUILD_MESSAGES"),
+    ];
+
+    pub const fn empty() -> Self {
+        Intents(0)
+    }
+
+    pub const fn all() -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < Self::NAMES.len() {
+            bits |= Self::NAMES[i].0 .0;
+            i += 1;
+        }
+        Intents(bits)
+    }
+
+    pub fn from_bits(bits: u64) -> Option<Self> {
+        (bits & !Self::all().0 == 0).then_some(Intents(bits))
+    }
+
+    /// Like [`Intents::from_bits`], but drops unknown bits instead of failing.
+    pub const fn from_bits_truncate(bits: u64) -> Self {
+        Intents(bits & Self::all().0)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Intents) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Intents in `self` but not in `other`.
+    pub const fn difference(self, other: Intents) -> Intents {
+        Intents(self.0 & !other.0)
+    }
+}
+
+impl BitOr for Intents {
+    type Output = Intents;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Intents(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Intents {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl From<Intents> for u64 {
+    fn from(intents: Intents) -> Self {
+        intents.0
+    }
+}
+
+// QQ may grant intents added after this list was written, which shouldn't fail the whole payload.
+impl<'de> Deserialize<'de> for Intents {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Self::from_bits_truncate)
+    }
+}
+
+impl Debug for Intents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = Self::NAMES
+            .iter()
+            .filter(|(intent, _)| self.contains(*intent))
+            .map(|(_, name)| *name);
+        f.debug_set().entries(names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_intents() {
+        let mut intents = Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE;
+        assert!(intents.contains(Intents::PUBLIC_GUILD_MESSAGES));
+        assert!(intents.contains(Intents::DIRECT_MESSAGE));
+        assert!(!intents.contains(Intents::GUILDS));
+        intents |= Intents::GUILDS;
+        assert!(intents.contains(Intents::GUILDS | Intents::DIRECT_MESSAGE));
+        assert_eq!(
+            intents.difference(Intents::GUILDS | Intents::PUBLIC_GUILD_MESSAGES),
+            Intents::DIRECT_MESSAGE
+        );
+        assert!(intents.difference(Intents::all()).is_empty());
+        assert_eq!(
+            format!("{intents:?}"),
+            r#"{"GUILDS", "DIRECT_MESSAGE", "PUBLIC_GUILD_MESSAGES"}"#
+        );
+    }
+
+    #[test]
+    fn test_convert_intents() {
+        let intents = Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE;
+        assert_eq!(u64::from(intents), (1 << 30) | (1 << 12));
+        assert_eq!(u64::from(Intents::empty()), 0);
+        assert_eq!(Intents::from_bits((1 << 30) | (1 << 12)), Some(intents));
+        assert_eq!(Intents::from_bits(1 << 2), None);
+        assert_eq!(
+            Intents::from_bits(Intents::all().bits()),
+            Some(Intents::all())
+        );
+        assert_eq!(
+            Intents::from_bits_truncate((1 << 30) | (1 << 2)),
+            Intents::PUBLIC_GUILD_MESSAGES
+        );
+    }
+
+    #[test]
+    fn test_deserialize_intents() {
+        let intents: Intents =
+            serde_json::from_str(&((1u64 << 30) | (1 << 12)).to_string()).unwrap();
+        assert_eq!(
+            intents,
+            Intents::PUBLIC_GUILD_MESSAGES | Intents::DIRECT_MESSAGE
+        );
+        let intents: Intents =
+            serde_json::from_str(&((1u64 << 30) | (1 << 2)).to_string()).unwrap();
+        assert_eq!(intents, Intents::PUBLIC_GUILD_MESSAGES);
+        assert!(serde_json::from_str::<Intents>("-1").is_err());
+    }
+}
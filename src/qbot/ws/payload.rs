@@ -3,6 +3,8 @@ use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
 use super::opcode::{OpCode, OpCodePayload};
+use super::Intents;
+use crate::qbot::{ChannelId, GuildId, MessageId};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(super) struct QBotWebSocketAnyPayload {
@@ -45,7 +47,7 @@ impl OpCodePayload for IdentifyPayload<'_> {
     const OPCODE: OpCode = OpCode::OP_IDENTIFY;
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ReadyUser {
     pub id: String,
     pub username: String,
@@ -57,6 +59,9 @@ pub struct ReadyPayload {
     pub session_id: String,
     pub user: ReadyUser,
     pub shard: (u32, u32),
+    /// Intents actually granted, which lack the ones the bot has no permission for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intents: Option<Intents>,
 }
 impl OpCodePayload for ReadyPayload {
     const OPCODE: OpCode = OpCode::OP_DISPATCH;
@@ -88,14 +93,15 @@ impl OpCodePayload for ResumePayload<'_> {
 pub struct AtMessageCreateAuthor {
     #[serde(rename = "avatar")]
     pub avatar_url: String,
-    #[serde(default)]
+    #[serde(rename = "bot", default)]
     pub is_bot: Option<bool>,
     pub id: String,
     pub username: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AtMessageCreateMember {
+    #[serde(default)]
     pub joined_at: String,
     #[serde(default)]
     pub roles: Vec<String>,
@@ -104,22 +110,103 @@ pub struct AtMessageCreateMember {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AtMessageCreatePayload {
     pub author: AtMessageCreateAuthor,
-    pub channel_id: String,
+    pub channel_id: ChannelId,
     pub content: String,
-    pub guild_id: String,
-    pub id: String,
+    pub guild_id: GuildId,
+    pub id: MessageId,
+    #[serde(default)]
     pub member: AtMessageCreateMember,
+    #[serde(default)]
     pub timestamp: String,
+    #[serde(default)]
     pub seq: i32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DirectMessageCreatePayload {
     pub author: AtMessageCreateAuthor,
-    pub channel_id: String,
+    pub channel_id: ChannelId,
     pub content: String,
-    pub guild_id: String,
-    pub id: String,
+    pub guild_id: GuildId,
+    pub id: MessageId,
+    #[serde(default)]
     pub member: AtMessageCreateMember,
+    #[serde(default)]
     pub timestamp: String,
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedMessage {
+    pub id: MessageId,
+    pub channel_id: ChannelId,
+    #[serde(default)]
+    pub guild_id: GuildId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageDeletePayload {
+    pub message: DeletedMessage,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_author_bot_field() {
+        let author: AtMessageCreateAuthor = serde_json::from_value(json!({
+            "avatar": "https://example.com/avatar.png",
+            "bot": true,
+            "id": "1",
+            "username": "bot",
+        }))
+        .unwrap();
+        assert_eq!(author.is_bot, Some(true));
+
+        let author: AtMessageCreateAuthor = serde_json::from_value(json!({
+            "avatar": "https://example.com/avatar.png",
+            "id": "2",
+            "username": "user",
+        }))
+        .unwrap();
+        assert_eq!(author.is_bot, None);
+        assert_eq!(author.avatar_url, "https://example.com/avatar.png");
+    }
+
+    #[test]
+    fn test_minimal_at_message() {
+        let payload: AtMessageCreatePayload = serde_json::from_value(json!({
+            "author": {
+                "avatar": "https://example.com/avatar.png",
+                "id": "1",
+                "username": "user",
+            },
+            "channel_id": "channelId",
+            "content": "<@!2> 帮助",
+            "guild_id": "guildId",
+            "id": "messageId",
+        }))
+        .unwrap();
+        assert_eq!(payload.content, "<@!2> 帮助");
+        assert_eq!(payload.member, AtMessageCreateMember::default());
+        assert_eq!(payload.timestamp, "");
+        assert_eq!(payload.seq, 0);
+
+        let payload: AtMessageCreatePayload = serde_json::from_value(json!({
+            "author": {
+                "avatar": "https://example.com/avatar.png",
+                "id": "1",
+                "username": "user",
+            },
+            "channel_id": "channelId",
+            "content": "帮助",
+            "guild_id": "guildId",
+            "id": "messageId",
+            "member": { "joined_at": "2024-04-13T16:16:00+08:00" },
+        }))
+        .unwrap();
+        assert!(payload.member.roles.is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+
+use tracing::Level;
+
+#[derive(Clone, Default)]
+struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Log events recorded on the current thread until this is dropped.
+pub(crate) struct CapturedLogs {
+    writer: CaptureWriter,
+    _guard: tracing::subscriber::DefaultGuard,
+}
+
+pub(crate) fn capture_logs() -> CapturedLogs {
+    let writer = CaptureWriter::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer({
+            let writer = writer.clone();
+            move || writer.clone()
+        })
+        .finish();
+    CapturedLogs {
+        writer,
+        _guard: tracing::subscriber::set_default(subscriber),
+    }
+}
+
+impl CapturedLogs {
+    pub(crate) fn output(&self) -> String {
+        String::from_utf8(self.writer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// Returns the first line logged at `level` that contains `message`, and panics if there is
+    /// none.
+    pub(crate) fn find(&self, level: Level, message: &str) -> String {
+        let output = self.output();
+        output
+            .lines()
+            .find(|line| line.contains(level.as_str()) && line.contains(message))
+            .unwrap_or_else(|| panic!("no {level} {message:?} in {output:?}"))
+            .into()
+    }
+}
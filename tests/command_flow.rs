@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use mockito::{Matcher, Server};
+use serde_json::json;
+
+use rust_cn_qbot::controller::{Controller, ControllerImpl};
+use rust_cn_qbot::crawler::{CrawlerImpl, InMemoryCrawler};
+use rust_cn_qbot::handler::EventHandler;
+use rust_cn_qbot::post::{DailyPost, DailyPostDate};
+use rust_cn_qbot::qbot::model::Channel;
+use rust_cn_qbot::qbot::ws::payload::AtMessageCreatePayload;
+use rust_cn_qbot::qbot::ws::QBotWsMessageHandler;
+use rust_cn_qbot::qbot::{InMemoryApiClient, QBotApiClientImpl, QBotApiResult, QBotAuthorizer};
+
+struct StaticAuthorizer;
+
+impl QBotAuthorizer for StaticAuthorizer {
+    async fn get_access_token(&self) -> QBotApiResult<String> {
+        Ok("accessToken".into())
+    }
+}
+
+fn message(content: &str) -> AtMessageCreatePayload {
+    serde_json::from_value(json!({
+        "author": { "avatar": "", "id": "authorId", "username": "user" },
+        "channel_id": "channelId",
+        "content": content,
+        "guild_id": "guildId",
+        "id": "messageId",
+        "member": { "joined_at": "2024-01-01T00:00:00+08:00", "roles": ["admin"] },
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_crawl_command_replies() {
+    let mut rustcc_server = Server::new_async().await;
+    let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+    let crawl_mock = rustcc_server
+        .mock("GET", href)
+        .with_body(include_str!("fixtures/rustcc_daily_post_article.html"))
+        .create_async()
+        .await;
+    let mut api_server = Server::new_async().await;
+    let reply_mock = api_server
+        .mock("POST", "/channels/channelId/messages")
+        .match_header("Authorization", "QQBot accessToken")
+        .match_body(Matcher::Json(json!({
+            "msg_id": "messageId",
+            "msg_seq": 1,
+            "content": "爬取成功: 2024-04-11 - TinyUFO - 无锁高性能缓存",
+        })))
+        .with_header("content-type", "application/json")
+        .with_body(json!({}).to_string())
+        .create_async()
+        .await;
+
+    let api_client = Arc::new(QBotApiClientImpl::new(
+        api_server.url(),
+        "appId",
+        StaticAuthorizer,
+    ));
+    let controller = ControllerImpl::new(
+        api_client.clone(),
+        CrawlerImpl::new(rustcc_server.url()),
+        "newsChannelId".into(),
+        false,
+    );
+    let mut handler = EventHandler::new(api_client, controller, Some("admin".into()));
+
+    handler.handle_at_message(message(&format!("<@!1234> 爬取 {href}")));
+    assert!(handler.shutdown(Duration::from_secs(5)).await);
+
+    crawl_mock.assert_async().await;
+    reply_mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_send_records_thread() {
+    let mut rustcc_server = Server::new_async().await;
+    let href = "/article?id=325542e0-9d74-47a5-ba3d-a5cb485b1b99";
+    rustcc_server
+        .mock("GET", href)
+        .with_body(include_str!("fixtures/rustcc_daily_post_article.html"))
+        .create_async()
+        .await;
+
+    let api_client = Arc::new(InMemoryApiClient::new());
+    api_client.add_channel(news_channel());
+    let controller = ControllerImpl::new(
+        api_client.clone(),
+        CrawlerImpl::new(rustcc_server.url()),
+        "newsChannelId".into(),
+        false,
+    );
+
+    controller.爬取(href).await;
+    let reply = controller
+        .发送(
+            &"guildId".into(),
+            &"channelId".into(),
+            "2024-04-11".parse().unwrap(),
+            &[],
+        )
+        .await;
+    assert_eq!(reply, "发送成功: 2024-04-11 - TinyUFO - 无锁高性能缓存");
+
+    let threads = api_client.threads();
+    assert_eq!(threads.len(), 1);
+    let (channel_id, title, html) = &threads[0];
+    assert_eq!(channel_id, "newsChannelId");
+    assert_eq!(title, "[2024-04-11] TinyUFO - 无锁高性能缓存");
+    assert!(!html.is_empty());
+}
+
+fn news_channel() -> Channel {
+    Channel {
+        id: "newsChannelId".into(),
+        guild_id: "guildId".into(),
+        name: "日报".into(),
+        channel_type: Channel::TYPE_FORUM,
+    }
+}
+
+fn post(date: &str, title: &str) -> DailyPost {
+    DailyPost {
+        href: format!("/article?id={date}"),
+        content_html: format!("<p>{title}</p>"),
+        title: title.into(),
+        author: "PsiACE".into(),
+        publish_time: format!("{date} 16:16"),
+        published_at: None,
+        date: date.parse::<DailyPostDate>().unwrap(),
+        tags: vec![],
+        content_hash: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_crawl_and_send_offline() {
+    let api_client = Arc::new(InMemoryApiClient::new());
+    api_client.add_channel(news_channel());
+    let crawler = InMemoryCrawler::new(vec![
+        post("2024-04-11", "TinyUFO"),
+        post("2024-04-12", "RustConf 2024"),
+    ]);
+    let controller =
+        ControllerImpl::new(api_client.clone(), crawler, "newsChannelId".into(), false);
+
+    assert_eq!(
+        controller.爬取("/article?id=2024-04-11").await,
+        "爬取成功: 2024-04-11 - TinyUFO"
+    );
+    assert_eq!(
+        controller.爬取最新().await,
+        "爬取成功: 2024-04-12 - RustConf 2024"
+    );
+    let reply = controller
+        .发送(
+            &"guildId".into(),
+            &"channelId".into(),
+            "2024-04-12".parse().unwrap(),
+            &[],
+        )
+        .await;
+    assert_eq!(reply, "发送成功: 2024-04-12 - RustConf 2024");
+
+    let threads = api_client.threads();
+    assert_eq!(threads.len(), 1);
+    let (channel_id, title, html) = &threads[0];
+    assert_eq!(channel_id, "newsChannelId");
+    assert_eq!(title, "[2024-04-12] RustConf 2024");
+    assert!(html.contains("RustConf 2024"));
+}